@@ -0,0 +1,44 @@
+extern crate ears;
+
+use std::io::stdin;
+use std::io::stdout;
+use std::io::Write;
+
+use ears::{device, AudioController, Sound};
+
+fn main() {
+    let devices = device::get_devices();
+    let default = device::get_default_device();
+
+    for (i, name) in devices.iter().enumerate() {
+        match default {
+            Some(ref default) if default == name => println!("{}: {} (default)", i, name),
+            _ => println!("{}: {}", i, name),
+        }
+    }
+
+    print!("Choose an output device: ");
+    stdout().flush().ok();
+
+    let mut line = String::new();
+    stdin().read_line(&mut line).ok();
+
+    match line
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| devices.get(i))
+    {
+        Some(name) => ears::init_with_device(name).expect("Cannot open the device"),
+        None => ears::init().expect("Cannot open the default device"),
+    };
+
+    println!(
+        "Playing on {}",
+        device::get_current_device().unwrap_or_default()
+    );
+
+    let mut snd = Sound::new("res/explosion.wav").unwrap();
+    snd.play();
+    while snd.is_playing() {}
+}
@@ -0,0 +1,24 @@
+extern crate ears;
+
+use ears::{AudioController, Loopback, ReverbEffect, ReverbPreset, Sound};
+use std::time::Duration;
+
+// Renders a short scene to a WAV file without using the sound card.
+fn main() {
+    let mut loopback = Loopback::new(44100, 2).expect("Cannot create the loopback device");
+
+    let reverb_effect = ReverbEffect::preset(ReverbPreset::Cave.properties()).ok();
+
+    let mut explosion = Sound::new("res/explosion.wav").unwrap();
+    explosion.connect(&reverb_effect);
+    explosion.play();
+
+    let mut yell = Sound::new("res/yell.ogg").unwrap();
+    yell.connect(&reverb_effect);
+    yell.set_position([5.0, 0.0, -5.0]);
+    yell.play();
+
+    if loopback.render_to_file("loopback.wav", Duration::from_secs(3)) {
+        println!("Scene rendered to loopback.wav");
+    }
+}
@@ -0,0 +1,269 @@
+//! Explicit creation and configuration of the audio context.
+
+use hrtf::{self, Hrtf};
+use internal::{OpenAlContextError, OpenAlData};
+use openal::ffi;
+
+use error::SoundError;
+use music::Music;
+use sound::Sound;
+use sound_data::SoundData;
+
+/**
+ * Configure the audio context before creating it.
+ *
+ * Every attribute left unset is chosen by the implementation.
+ *
+ * # Example
+ * ```no_run
+ * use ears::AudioContext;
+ *
+ * fn main() -> Result<(), ears::OpenAlContextError> {
+ *     let context = AudioContext::builder()
+ *         .frequency(48000)
+ *         .mono_sources(128)
+ *         .stereo_sources(8)
+ *         .build()?;
+ *
+ *     println!("Output frequency: {}", context.frequency());
+ *     Ok(())
+ * }
+ * ```
+ */
+#[derive(Clone, Debug, Default)]
+pub struct AudioContextBuilder {
+    device: Option<String>,
+    frequency: Option<i32>,
+    refresh: Option<i32>,
+    mono_sources: Option<i32>,
+    stereo_sources: Option<i32>,
+    max_auxiliary_sends: Option<i32>,
+    hrtf: Option<Hrtf>,
+}
+
+impl AudioContextBuilder {
+    /// Create a builder with every attribute left to the implementation.
+    pub fn new() -> AudioContextBuilder {
+        Default::default()
+    }
+
+    /// Open the device with the given name, as returned by
+    /// `ears::device::get_devices()`, instead of the default device.
+    pub fn device(mut self, name: &str) -> AudioContextBuilder {
+        self.device = Some(name.to_string());
+        self
+    }
+
+    /// Set the output frequency of the mixer, in Hz.
+    pub fn frequency(mut self, frequency: i32) -> AudioContextBuilder {
+        self.frequency = Some(frequency);
+        self
+    }
+
+    /// Set the refresh rate of the mixer, in Hz.
+    pub fn refresh(mut self, refresh: i32) -> AudioContextBuilder {
+        self.refresh = Some(refresh);
+        self
+    }
+
+    /// Set the number of sources able to play mono buffers.
+    pub fn mono_sources(mut self, count: i32) -> AudioContextBuilder {
+        self.mono_sources = Some(count);
+        self
+    }
+
+    /// Set the number of sources able to play stereo (or more) buffers.
+    pub fn stereo_sources(mut self, count: i32) -> AudioContextBuilder {
+        self.stereo_sources = Some(count);
+        self
+    }
+
+    /// Set the number of auxiliary sends per source, each ReverbEffect
+    /// connected at once to a source uses one send.
+    pub fn max_auxiliary_sends(mut self, count: i32) -> AudioContextBuilder {
+        self.max_auxiliary_sends = Some(count);
+        self
+    }
+
+    /// Request a HRTF configuration, see `ears::hrtf`.
+    pub fn hrtf(mut self, hrtf: Hrtf) -> AudioContextBuilder {
+        self.hrtf = Some(hrtf);
+        self
+    }
+
+    /// Build the attribute list passed to alcCreateContext.
+    fn attributes(&self) -> Vec<i32> {
+        let mut attributes = Vec::new();
+        let pairs = [
+            (ffi::ALC_FREQUENCY, self.frequency),
+            (ffi::ALC_REFRESH, self.refresh),
+            (ffi::ALC_MONO_SOURCES, self.mono_sources),
+            (ffi::ALC_STEREO_SOURCES, self.stereo_sources),
+            (ffi::ALC_MAX_AUXILIARY_SENDS, self.max_auxiliary_sends),
+        ];
+        for &(key, value) in pairs.iter() {
+            if let Some(value) = value {
+                attributes.push(key);
+                attributes.push(value);
+            }
+        }
+        match self.hrtf {
+            // The datasets can't be listed before the device is opened
+            Some(Hrtf::Dataset(_)) | None => {}
+            Some(ref hrtf) => {
+                attributes.extend(hrtf::get_attributes(hrtf).unwrap_or_default());
+            }
+        }
+        attributes
+    }
+
+    /**
+     * Create the audio context.
+     *
+     * Must be called before any other use of __ears__, otherwise the context
+     * is already created with the default attributes.
+     *
+     * # Return
+     * `Ok(AudioContext)` on success, `Err(OpenAlContextError)` otherwise.
+     */
+    pub fn build(self) -> Result<AudioContext, OpenAlContextError> {
+        let attributes = self.attributes();
+        OpenAlData::init_with(self.device.as_ref().map(|name| &name[..]), attributes)?;
+        if let Some(Hrtf::Dataset(name)) = self.hrtf {
+            hrtf::set_hrtf(Hrtf::Dataset(name))?;
+        }
+        Ok(AudioContext { _private: () })
+    }
+}
+
+/**
+ * Handle to the audio context.
+ *
+ * __ears__ uses a single global audio context, created either implicitly by
+ * the first Sound or Music or explicitly with `AudioContext::builder()`.
+ * Every Sound and Music plays in that context, however it is created: the
+ * handle holds no state and only exists to read the attributes actually
+ * granted by the implementation. `sound`, `sound_data` and `music` are the
+ * same as `Sound::new`, `SoundData::new` and `Music::new`, they only make it
+ * explicit that the context is built first.
+ *
+ * # Example
+ * ```no_run
+ * use ears::{AudioContext, AudioController, SoundError};
+ *
+ * fn main() -> Result<(), SoundError> {
+ *     let context = AudioContext::builder()
+ *         .frequency(44100)
+ *         .build()
+ *         .map_err(|_| SoundError::InvalidOpenALContext)?;
+ *
+ *     let mut snd = context.sound("path/to/my/sound.ogg")?;
+ *     snd.play();
+ *     Ok(())
+ * }
+ * ```
+ */
+#[derive(Clone, Debug)]
+pub struct AudioContext {
+    _private: (),
+}
+
+impl AudioContext {
+    /// Get a builder to configure the audio context.
+    pub fn builder() -> AudioContextBuilder {
+        AudioContextBuilder::new()
+    }
+
+    /**
+     * Get the current audio context.
+     *
+     * The context is created with the default attributes if it doesn't exist
+     * yet, like `ears::init()` does.
+     */
+    pub fn current() -> Result<AudioContext, OpenAlContextError> {
+        OpenAlData::check_al_context()?;
+        Ok(AudioContext { _private: () })
+    }
+
+    fn granted_attribute(&self, key: i32) -> i32 {
+        OpenAlData::granted_attributes()
+            .into_iter()
+            .find(|&(k, _)| k == key)
+            .map(|(_, value)| value)
+            .unwrap_or(0)
+    }
+
+    /// Get the output frequency of the mixer, in Hz.
+    pub fn frequency(&self) -> i32 {
+        self.granted_attribute(ffi::ALC_FREQUENCY)
+    }
+
+    /// Get the refresh rate of the mixer, in Hz.
+    pub fn refresh(&self) -> i32 {
+        self.granted_attribute(ffi::ALC_REFRESH)
+    }
+
+    /// Get the number of sources able to play mono buffers.
+    pub fn mono_sources(&self) -> i32 {
+        self.granted_attribute(ffi::ALC_MONO_SOURCES)
+    }
+
+    /// Get the number of sources able to play stereo (or more) buffers.
+    pub fn stereo_sources(&self) -> i32 {
+        self.granted_attribute(ffi::ALC_STEREO_SOURCES)
+    }
+
+    /// Get the number of auxiliary sends per source.
+    pub fn max_auxiliary_sends(&self) -> i32 {
+        self.granted_attribute(ffi::ALC_MAX_AUXILIARY_SENDS)
+    }
+
+    /// Get the name of the device the context plays on.
+    pub fn device_name(&self) -> Option<String> {
+        OpenAlData::current_device_name()
+    }
+
+    /// Create a new Sound in the global context, same as `Sound::new`.
+    pub fn sound(&self, path: &str) -> Result<Sound, SoundError> {
+        Sound::new(path)
+    }
+
+    /// Create a new SoundData in the global context, same as `SoundData::new`.
+    pub fn sound_data(&self, path: &str) -> Result<SoundData, SoundError> {
+        SoundData::new(path)
+    }
+
+    /// Create a new Music in the global context, same as `Music::new`.
+    pub fn music(&self, path: &str) -> Result<Music, SoundError> {
+        Music::new(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use audio_context::AudioContext;
+    use headless;
+    use hrtf::Hrtf;
+
+    #[test]
+    fn audio_context_builder_attributes() -> () {
+        let builder = AudioContext::builder().frequency(48000).stereo_sources(4);
+        assert_eq!(builder.attributes(), vec![0x1007, 48000, 0x1011, 4]);
+    }
+
+    #[test]
+    fn audio_context_builder_hrtf_attributes() -> () {
+        let builder = AudioContext::builder().hrtf(Hrtf::Disabled);
+        assert_eq!(builder.attributes(), vec![0x1992, 0]);
+    }
+
+    #[test]
+    fn audio_context_current_OK() -> () {
+        let _harness = headless::lock();
+
+        let context = AudioContext::current().unwrap();
+        assert_eq!(context.frequency(), headless::SAMPLE_RATE);
+    }
+}
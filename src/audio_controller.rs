@@ -21,8 +21,11 @@
 
 //! The functionnality that a Sound or a Music should provide.
 
+use std::sync::mpsc::Receiver;
 use std::time::Duration;
 
+use fade::FadeCurve;
+use markers::Marker;
 use reverb_effect::ReverbEffect;
 use states::State;
 
@@ -37,6 +40,22 @@ pub trait AudioController {
     /// Stop the Audio Source.
     fn stop(&mut self) -> ();
 
+    /**
+     * Fade the volume of the Audio Source out, then stop it.
+     *
+     * The volume goes back to its value before the fade once the Audio
+     * Source is stopped, for its next play.
+     *
+     * The default implementation stops right away, Sound and Music fade.
+     *
+     * # Argument
+     * * `duration` - The duration of the fade
+     */
+    fn stop_with_fade(&mut self, duration: Duration) -> () {
+        let _ = duration;
+        self.stop();
+    }
+
     /// Connect a ReverbEffect to the Source
     fn connect(&mut self, reverb_effect: &Option<ReverbEffect>);
 
@@ -92,6 +111,26 @@ pub trait AudioController {
      */
     fn get_volume(&self) -> f32;
 
+    /**
+     * Change the volume of the Audio Source progressively.
+     *
+     * The fade is applied by a background thread, it stays smooth whatever
+     * the pace of the application. Setting the volume or stopping the Audio
+     * Source cancels it.
+     *
+     * The default implementation sets the volume right away, Sound and
+     * Music fade.
+     *
+     * # Arguments
+     * * `volume` - The volume to reach, should be between 0.0 and 1.0
+     * * `duration` - The duration of the fade
+     * * `curve` - The shape of the fade
+     */
+    fn fade_to(&mut self, volume: f32, duration: Duration, curve: FadeCurve) -> () {
+        let _ = (duration, curve);
+        self.set_volume(volume);
+    }
+
     /**
      * Set the minimal volume for a Audio Source.
      *
@@ -171,6 +210,25 @@ pub trait AudioController {
      */
     fn get_pitch(&self) -> f32;
 
+    /**
+     * Change the pitch of the source progressively.
+     *
+     * Like fade_to, setting the pitch or stopping the Audio Source cancels
+     * the change.
+     *
+     * The default implementation sets the pitch right away, Sound and Music
+     * change it progressively.
+     *
+     * # Arguments
+     * * `pitch` - The pitch to reach in the range [0.5 - 2.0]
+     * * `duration` - The duration of the change
+     * * `curve` - The shape of the change
+     */
+    fn pitch_to(&mut self, pitch: f32, duration: Duration, curve: FadeCurve) -> () {
+        let _ = (duration, curve);
+        self.set_pitch(pitch);
+    }
+
     /**
      * Set the position of the Audio Source relative to the listener or absolute.
      *
@@ -344,3 +402,76 @@ pub trait AudioController {
      */
     fn get_duration(&self) -> Duration;
 }
+
+/**
+ * The functionality of an Audio Source to follow and move its playback.
+ *
+ * Implemented by Sound and Music. It is kept apart from AudioController so
+ * the existing implementors of AudioController don't have to provide it.
+ */
+pub trait AudioControllerExt: AudioController {
+    /**
+     * Call a function once the Audio Source stops.
+     *
+     * The function is called from a background thread when the Audio Source
+     * reaches its end or is stopped. If it isn't playing yet, it is called
+     * once the Audio Source played then stopped. It is never called if the
+     * Audio Source is dropped first.
+     *
+     * # Argument
+     * * `callback` - The function to call
+     */
+    fn on_finished<F>(&mut self, callback: F) -> ()
+    where
+        F: FnOnce() + Send + 'static,
+        Self: Sized;
+
+    /**
+     * Get a channel receiving a message once the Audio Source stops.
+     *
+     * Like on_finished, without a callback.
+     *
+     * # Return
+     * The Receiver end of the channel, disconnected without a message if the
+     * Audio Source is dropped first.
+     */
+    fn finished(&mut self) -> Receiver<()>;
+
+    /**
+     * Call a function each time the playback crosses a marker.
+     *
+     * The markers are the cue points of the file and the ones added with
+     * add_marker. The function is called from a background thread, a few
+     * milliseconds after the marker is heard, and replaces the previous one.
+     *
+     * # Argument
+     * * `callback` - The function to call with the crossed marker
+     */
+    fn on_marker<F>(&mut self, callback: F) -> ()
+    where
+        F: FnMut(&Marker) + Send + 'static,
+        Self: Sized;
+
+    /**
+     * Move the playback to a time.
+     *
+     * Unlike set_offset, the position doesn't depend on the sample rate and
+     * doesn't overflow for long files.
+     *
+     * # Argument
+     * * `position` - The time from the start of the Audio Source
+     */
+    fn seek(&mut self, position: Duration) -> ();
+
+    /**
+     * Get the time being heard.
+     *
+     * The latency of the device is taken into account when the
+     * AL_SOFT_source_latency extension is available.
+     *
+     * # Return
+     * The time from the start of the Audio Source, on the same scale as
+     * get_duration.
+     */
+    fn position(&self) -> Duration;
+}
@@ -0,0 +1,282 @@
+//! Musical time of a Music, in bars, beats and ticks.
+
+use std::time::Duration;
+
+/// Default number of ticks in a beat, the common MIDI resolution.
+const DEFAULT_TICKS_PER_BEAT: u32 = 480;
+
+/// Slowest tempo a clock accepts, so the durations of its bars fit in a
+/// Duration.
+const MIN_BPM: f64 = 1e-3;
+
+/// Margin absorbing the rounding of the positions computed from frames,
+/// so a position right on a beat isn't reported at the end of the previous
+/// one.
+const EPSILON: f64 = 1e-6;
+
+/**
+ * The grid a scheduled change is aligned on.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quantize {
+    /// The start of the next beat.
+    Beat,
+    /// The start of the next bar.
+    Bar,
+}
+
+/**
+ * A position in musical time.
+ *
+ * Every field starts at 0: bar 0, beat 0, tick 0 is the first downbeat.
+ * The frames played before the first downbeat, like a pickup, are in the
+ * negative bars.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeatPosition {
+    /// The bar, negative before the first downbeat
+    pub bar: i64,
+    /// The beat in the bar
+    pub beat: u32,
+    /// The tick in the beat
+    pub tick: u32,
+}
+
+/**
+ * The tempo and time signature of a piece.
+ *
+ * The tempo is constant, the beats being counted from the downbeat offset,
+ * the time of the first beat of the first bar in the file.
+ *
+ * # Example
+ * ```no_run
+ * use ears::{AudioController, BeatClock, Music, Quantize, SoundError};
+ * use std::time::Duration;
+ *
+ * fn main() -> Result<(), SoundError> {
+ *     let mut msc = Music::new("path/to/theme.ogg")?;
+ *     let clock = BeatClock::new(120., 4, 4).expect("Invalid tempo");
+ *     msc.set_beat_clock(clock.downbeat(Duration::from_millis(250)));
+ *     msc.play();
+ *     msc.on_next(Quantize::Bar, |position| println!("bar {}", position.bar));
+ *     Ok(())
+ * }
+ * ```
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeatClock {
+    bpm: f64,
+    beats_per_bar: u32,
+    beat_unit: u32,
+    downbeat: Duration,
+    ticks_per_beat: u32,
+}
+
+impl BeatClock {
+    /**
+     * Create a clock with its first downbeat at the start of the file.
+     *
+     * # Arguments
+     * * `bpm` - The tempo, in beats per minute
+     * * `beats_per_bar` - The upper number of the time signature
+     * * `beat_unit` - The lower number of the time signature, the note
+     * value of a beat
+     *
+     * # Return
+     * The clock, None if bpm isn't a finite tempo of at least 0.001 beats
+     * per minute.
+     */
+    pub fn new(bpm: f64, beats_per_bar: u32, beat_unit: u32) -> Option<BeatClock> {
+        if !bpm.is_finite() || bpm < MIN_BPM {
+            return None;
+        }
+
+        Some(BeatClock {
+            bpm: bpm,
+            beats_per_bar: beats_per_bar.max(1),
+            beat_unit: beat_unit,
+            downbeat: Duration::from_secs(0),
+            ticks_per_beat: DEFAULT_TICKS_PER_BEAT,
+        })
+    }
+
+    /**
+     * Set the time of the first downbeat in the file.
+     *
+     * # Argument
+     * * `offset` - The time of the first beat of bar 0
+     */
+    pub fn downbeat(mut self, offset: Duration) -> BeatClock {
+        self.downbeat = offset;
+        self
+    }
+
+    /**
+     * Set the number of ticks in a beat, 480 by default.
+     *
+     * # Argument
+     * * `ticks` - The resolution of the beats, at least 1
+     */
+    pub fn ticks_per_beat(mut self, ticks: u32) -> BeatClock {
+        self.ticks_per_beat = ticks.max(1);
+        self
+    }
+
+    /// Get the tempo, in beats per minute.
+    pub fn get_bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Get the time signature, as (beats per bar, beat unit).
+    pub fn get_time_signature(&self) -> (u32, u32) {
+        (self.beats_per_bar, self.beat_unit)
+    }
+
+    /// Get the time of the first downbeat in the file.
+    pub fn get_downbeat(&self) -> Duration {
+        self.downbeat
+    }
+
+    /// Get the number of ticks in a beat.
+    pub fn get_ticks_per_beat(&self) -> u32 {
+        self.ticks_per_beat
+    }
+
+    /// Get the duration of a beat.
+    pub fn beat_duration(&self) -> Duration {
+        Duration::from_secs_f64(60. / self.bpm)
+    }
+
+    /// Get the duration of a bar.
+    pub fn bar_duration(&self) -> Duration {
+        Duration::from_secs_f64(60. / self.bpm * self.beats_per_bar as f64)
+    }
+
+    /**
+     * Get the musical position of a time of the file.
+     *
+     * # Argument
+     * * `time` - The time from the start of the file
+     *
+     * # Return
+     * The bar, beat and tick being played at that time.
+     */
+    pub fn position_at(&self, time: Duration) -> BeatPosition {
+        let ticks_per_bar = self.ticks_per_beat as i64 * self.beats_per_bar as i64;
+        let ticks =
+            (self.beats_since_downbeat(time) * self.ticks_per_beat as f64 + EPSILON).floor() as i64;
+        let in_bar = ticks.rem_euclid(ticks_per_bar);
+
+        BeatPosition {
+            bar: ticks.div_euclid(ticks_per_bar),
+            beat: (in_bar / self.ticks_per_beat as i64) as u32,
+            tick: (in_bar % self.ticks_per_beat as i64) as u32,
+        }
+    }
+
+    /**
+     * Get the time of the next beat or bar of the file.
+     *
+     * # Arguments
+     * * `time` - The time from the start of the file
+     * * `quantize` - Whether to look for the next beat or bar
+     *
+     * # Return
+     * The time of the first beat or bar starting strictly after time.
+     */
+    pub fn next(&self, time: Duration, quantize: Quantize) -> Duration {
+        let unit = match quantize {
+            Quantize::Beat => 1.,
+            Quantize::Bar => self.beats_per_bar as f64,
+        };
+        let count = (self.beats_since_downbeat(time) / unit + EPSILON).floor() + 1.;
+        let seconds = self.downbeat.as_secs_f64() + count * unit * 60. / self.bpm;
+        Duration::from_secs_f64(seconds.max(0.))
+    }
+
+    fn beats_since_downbeat(&self, time: Duration) -> f64 {
+        (time.as_secs_f64() - self.downbeat.as_secs_f64()) * self.bpm / 60.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use beat_clock::{BeatClock, BeatPosition, Quantize};
+    use std::time::Duration;
+
+    fn position(bar: i64, beat: u32, tick: u32) -> BeatPosition {
+        BeatPosition {
+            bar: bar,
+            beat: beat,
+            tick: tick,
+        }
+    }
+
+    #[test]
+    fn beat_clock_new() -> () {
+        assert_eq!(
+            BeatClock::new(90., 0, 4).unwrap().get_time_signature(),
+            (1, 4)
+        );
+        assert!(BeatClock::new(0., 4, 4).is_none());
+        assert!(BeatClock::new(-120., 4, 4).is_none());
+        assert!(BeatClock::new(1e-300, 4, 4).is_none());
+        assert!(BeatClock::new(f64::INFINITY, 4, 4).is_none());
+        assert!(BeatClock::new(f64::NAN, 4, 4).is_none());
+    }
+
+    #[test]
+    fn beat_clock_position_at() -> () {
+        // Half a second per beat, two seconds per bar
+        let clock = BeatClock::new(120., 4, 4).unwrap();
+
+        assert_eq!(clock.position_at(Duration::from_secs(0)), position(0, 0, 0));
+        assert_eq!(
+            clock.position_at(Duration::from_millis(750)),
+            position(0, 1, 240)
+        );
+        assert_eq!(
+            clock.position_at(Duration::from_millis(4500)),
+            position(2, 1, 0)
+        );
+        // Right on a beat computed from frames
+        assert_eq!(
+            clock.position_at(Duration::from_secs_f64(22050. / 44100.)),
+            position(0, 1, 0)
+        );
+    }
+
+    #[test]
+    fn beat_clock_downbeat() -> () {
+        let clock = BeatClock::new(120., 3, 4)
+            .unwrap()
+            .downbeat(Duration::from_millis(500))
+            .ticks_per_beat(4);
+
+        // The pickup is the last beat of bar -1
+        assert_eq!(
+            clock.position_at(Duration::from_millis(250)),
+            position(-1, 2, 2)
+        );
+        assert_eq!(
+            clock.position_at(Duration::from_millis(2000)),
+            position(1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn beat_clock_next() -> () {
+        let clock = BeatClock::new(120., 4, 4)
+            .unwrap()
+            .downbeat(Duration::from_millis(100));
+        let next = |millis, quantize| {
+            let next = clock.next(Duration::from_millis(millis), quantize);
+            (next.as_secs_f64() * 1000.).round() as u64
+        };
+
+        assert_eq!(next(0, Quantize::Beat), 100);
+        assert_eq!(next(700, Quantize::Beat), 1100);
+        // A position right on a bar waits for the next one
+        assert_eq!(next(2100, Quantize::Bar), 4100);
+    }
+}
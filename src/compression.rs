@@ -0,0 +1,315 @@
+//! Compressed storage of the SoundData samples.
+
+use libc::c_void;
+use std::sync::Mutex;
+
+use openal::{al, ffi};
+
+/// Frames per IMA4 block when the block size can't be chosen.
+const IMA4_DEFAULT_BLOCK_FRAMES: usize = 65;
+
+/// Frames per IMA4 block with AL_SOFT_block_alignment, larger blocks spend
+/// less space on their headers.
+const IMA4_LARGE_BLOCK_FRAMES: usize = 1017;
+
+const IMA4_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+const IMA4_INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+/**
+ * The compression used to store the samples of the SoundDatas.
+ *
+ * The samples are encoded when the SoundData is loaded, trading some
+ * quality for less memory. Compression needs the matching OpenAL extension
+ * and only applies to mono and stereo sounds, the samples are stored
+ * uncompressed otherwise.
+ *
+ * Musics are never compressed, they only keep a few seconds in memory.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Compression {
+    /// Keep the samples uncompressed, as chosen by the sample format.
+    #[default]
+    Uncompressed,
+    /// 8 bit µ-law samples, half the size of 16 bit samples
+    /// (AL_EXT_MULAW).
+    MuLaw,
+    /// 4 bit IMA ADPCM samples, about a quarter of the size of 16 bit
+    /// samples (AL_EXT_IMA4).
+    Ima4,
+}
+
+lazy_static! {
+    static ref COMPRESSION: Mutex<Compression> = Mutex::new(Compression::Uncompressed);
+}
+
+/**
+ * Set the compression used for the SoundDatas created from now on.
+ *
+ * # Argument
+ * * `compression` - The new compression, `Compression::Uncompressed` by
+ * default
+ *
+ * # Example
+ * ```no_run
+ * use ears::{Compression, SoundData, SoundError};
+ *
+ * fn main() -> Result<(), SoundError> {
+ *   ears::set_compression(Compression::Ima4);
+ *   let voice = SoundData::new("path/to/my/voice_line.ogg")?;
+ *   Ok(())
+ * }
+ * ```
+ */
+pub fn set_compression(compression: Compression) -> () {
+    match COMPRESSION.lock() {
+        Ok(mut guard) => *guard = compression,
+        Err(poisoned) => *poisoned.into_inner() = compression,
+    }
+}
+
+/**
+ * Get the compression used for the new SoundDatas.
+ *
+ * # Return
+ * The current compression.
+ */
+pub fn get_compression() -> Compression {
+    match COMPRESSION.lock() {
+        Ok(guard) => *guard,
+        Err(poisoned) => *poisoned.into_inner(),
+    }
+}
+
+/// Samples encoded in a compressed OpenAL format.
+pub struct Encoded {
+    data: Vec<u8>,
+    al_format: i32,
+    /// Frames per block of the block based formats, 0 otherwise
+    block_frames: i32,
+}
+
+impl Encoded {
+    /// Upload the encoded samples in an OpenAL buffer.
+    pub fn upload(&self, al_buffer: u32, sample_rate: i32) -> () {
+        if self.block_frames > 0 {
+            al::alBufferi(
+                al_buffer,
+                ffi::AL_UNPACK_BLOCK_ALIGNMENT_SOFT,
+                self.block_frames,
+            );
+        }
+        al::alBufferData(
+            al_buffer,
+            self.al_format,
+            self.data.as_ptr() as *mut c_void,
+            self.data.len() as i32,
+            sample_rate,
+        );
+    }
+}
+
+/// Get the OpenAL format samples with this channel count are compressed to,
+/// None if they stay uncompressed.
+pub fn get_compressed_format(channels: i32) -> Option<i32> {
+    let (format, extension) = match (get_compression(), channels) {
+        (Compression::MuLaw, 1) => (ffi::AL_FORMAT_MONO_MULAW_EXT, "AL_EXT_MULAW"),
+        (Compression::MuLaw, 2) => (ffi::AL_FORMAT_STEREO_MULAW_EXT, "AL_EXT_MULAW"),
+        (Compression::Ima4, 1) => (ffi::AL_FORMAT_MONO_IMA4, "AL_EXT_IMA4"),
+        (Compression::Ima4, 2) => (ffi::AL_FORMAT_STEREO_IMA4, "AL_EXT_IMA4"),
+        _ => return None,
+    };
+    if al::alIsExtensionPresent(extension) {
+        Some(format)
+    } else {
+        None
+    }
+}
+
+/// Encode interleaved 16 bit samples with the current compression, None if
+/// they stay uncompressed.
+pub fn encode(samples: &[i16], channels: i32) -> Option<Encoded> {
+    let al_format = get_compressed_format(channels)?;
+    match al_format {
+        ffi::AL_FORMAT_MONO_IMA4 | ffi::AL_FORMAT_STEREO_IMA4 => {
+            let block_frames = if al::alIsExtensionPresent("AL_SOFT_block_alignment") {
+                IMA4_LARGE_BLOCK_FRAMES
+            } else {
+                IMA4_DEFAULT_BLOCK_FRAMES
+            };
+            Some(Encoded {
+                data: encode_ima4(samples, channels as usize, block_frames),
+                al_format: al_format,
+                block_frames: if block_frames == IMA4_DEFAULT_BLOCK_FRAMES {
+                    0
+                } else {
+                    block_frames as i32
+                },
+            })
+        }
+        _ => Some(Encoded {
+            data: samples.iter().map(|sample| encode_mulaw(*sample)).collect(),
+            al_format: al_format,
+            block_frames: 0,
+        }),
+    }
+}
+
+/// Encode a sample in G.711 µ-law.
+fn encode_mulaw(sample: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 32635;
+
+    let mut sample = sample as i32;
+    let sign = if sample < 0 {
+        sample = -sample;
+        0x80
+    } else {
+        0
+    };
+    sample = sample.min(CLIP) + BIAS;
+
+    let mut exponent = 7;
+    let mut mask = 0x4000;
+    while sample & mask == 0 && exponent > 0 {
+        exponent -= 1;
+        mask >>= 1;
+    }
+    let mantissa = (sample >> (exponent + 3)) & 0x0F;
+
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+/// State of the IMA ADPCM encoder of one channel.
+#[derive(Clone, Copy)]
+struct Ima4Channel {
+    predictor: i32,
+    index: i32,
+}
+
+impl Ima4Channel {
+    /// Encode a sample in a nibble, updating the state like the decoder will.
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step = IMA4_STEP_TABLE[self.index as usize];
+        let mut diff = sample as i32 - self.predictor;
+        let mut nibble = 0;
+        if diff < 0 {
+            nibble = 8;
+            diff = -diff;
+        }
+
+        let mut mask = 4;
+        let mut temp_step = step;
+        while mask > 0 {
+            if diff >= temp_step {
+                nibble |= mask;
+                diff -= temp_step;
+            }
+            temp_step >>= 1;
+            mask >>= 1;
+        }
+
+        let mut delta = step >> 3;
+        if nibble & 4 != 0 {
+            delta += step;
+        }
+        if nibble & 2 != 0 {
+            delta += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            delta += step >> 2;
+        }
+        if nibble & 8 != 0 {
+            self.predictor -= delta;
+        } else {
+            self.predictor += delta;
+        }
+        self.predictor = self.predictor.clamp(-32768, 32767);
+        self.index = (self.index + IMA4_INDEX_TABLE[nibble as usize]).clamp(0, 88);
+
+        nibble as u8
+    }
+}
+
+/// Encode interleaved samples in WAV style IMA ADPCM blocks.
+///
+/// Each block starts with a header per channel holding the first sample,
+/// followed by the other samples as 4 byte chunks of 8 nibbles, alternating
+/// between the channels. The last block is padded with its last frame.
+fn encode_ima4(samples: &[i16], channels: usize, block_frames: usize) -> Vec<u8> {
+    let frames = samples.len() / channels;
+    let blocks = frames.div_ceil(block_frames);
+    let mut data = Vec::with_capacity(blocks * channels * (4 + (block_frames - 1) / 2));
+    let mut states = vec![
+        Ima4Channel {
+            predictor: 0,
+            index: 0,
+        };
+        channels
+    ];
+
+    let sample_at = |frame: usize, channel: usize| -> i16 {
+        if frames == 0 {
+            0
+        } else {
+            samples[frame.min(frames - 1) * channels + channel]
+        }
+    };
+
+    for block in 0..blocks {
+        let first = block * block_frames;
+
+        for (channel, state) in states.iter_mut().enumerate() {
+            let sample = sample_at(first, channel);
+            state.predictor = sample as i32;
+            data.push(sample as u8);
+            data.push((sample >> 8) as u8);
+            data.push(state.index as u8);
+            data.push(0);
+        }
+
+        let mut frame = first + 1;
+        while frame < first + block_frames {
+            for (channel, state) in states.iter_mut().enumerate() {
+                for pair in 0..4 {
+                    let low = state.encode(sample_at(frame + pair * 2, channel));
+                    let high = state.encode(sample_at(frame + pair * 2 + 1, channel));
+                    data.push(low | (high << 4));
+                }
+            }
+            frame += 8;
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use compression::{encode_ima4, encode_mulaw};
+
+    #[test]
+    fn compression_mulaw() -> () {
+        assert_eq!(encode_mulaw(0), 0xFF);
+        assert_eq!(encode_mulaw(-1), 0x7F);
+        assert_eq!(encode_mulaw(32767), 0x80);
+        assert_eq!(encode_mulaw(-32768), 0x00);
+    }
+
+    #[test]
+    fn compression_ima4_block_size() -> () {
+        // Two stereo blocks of 65 frames, 36 bytes per channel each
+        let samples = vec![1000i16; 100 * 2];
+        assert_eq!(encode_ima4(&samples, 2, 65).len(), 2 * 2 * 36);
+
+        // The header holds the first sample of the block
+        let data = encode_ima4(&samples, 1, 65);
+        assert_eq!((data[0], data[1]), (0xE8, 0x03));
+    }
+}
@@ -0,0 +1,314 @@
+//! Module to list and query the audio output devices.
+//!
+//! By default __ears__ opens the default output device of the system, use
+//! `ears::init_with_device` with one of the names returned by `get_devices`
+//! to play the audio on another device, or `switch_device` to move the
+//! playback once __ears__ is running.
+
+use internal::{OpenAlContextError, OpenAlData};
+use libc::c_void;
+use openal::{al, ffi};
+use std::mem;
+use std::ops::Deref;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The connection events of the output device.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum DeviceEvent {
+    /// The output device was disconnected, nothing can be heard anymore
+    Disconnected,
+    /// The output device is connected again, after a `switch_device` for
+    /// example
+    Reconnected,
+}
+
+/// Check which enumeration extension is available and return the
+/// parameters to query the device list and the default device.
+fn enumeration_params() -> Option<(i32, i32)> {
+    if al::alcIsExtensionPresent(0, "ALC_ENUMERATE_ALL_EXT") {
+        Some((
+            ffi::ALC_ALL_DEVICES_SPECIFIER,
+            ffi::ALC_DEFAULT_ALL_DEVICES_SPECIFIER,
+        ))
+    } else if al::alcIsExtensionPresent(0, "ALC_ENUMERATION_EXT") {
+        Some((ffi::ALC_DEVICE_SPECIFIER, ffi::ALC_DEFAULT_DEVICE_SPECIFIER))
+    } else {
+        None
+    }
+}
+
+/**
+ * Get the names of all the available output devices.
+ *
+ * Uses the ALC_ENUMERATE_ALL_EXT extension when available, which lists every
+ * individual output, otherwise falls back to ALC_ENUMERATION_EXT.
+ *
+ * # Return
+ * A Vec containing the name of each device, empty if the implementation
+ * doesn't support device enumeration.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device;
+ * for name in device::get_devices() {
+ *     println!("Output device: {}", name);
+ * }
+ * ```
+ */
+pub fn get_devices() -> Vec<String> {
+    match enumeration_params() {
+        Some((list_param, _)) => al::alcGetStringList(0, list_param),
+        None => Vec::new(),
+    }
+}
+
+/**
+ * Get the name of the default output device.
+ *
+ * # Return
+ * The name of the default device, or None if the implementation doesn't
+ * support device enumeration.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device;
+ * if let Some(name) = device::get_default_device() {
+ *     println!("Default output device: {}", name);
+ * }
+ * ```
+ */
+pub fn get_default_device() -> Option<String> {
+    match enumeration_params() {
+        Some((_, default_param)) => al::alcGetString(0, default_param),
+        None => None,
+    }
+}
+
+/**
+ * Get the name of the device __ears__ is currently playing on.
+ *
+ * # Return
+ * The name of the device, or None if __ears__ isn't initialized yet.
+ */
+pub fn get_current_device() -> Option<String> {
+    OpenAlData::current_device_name()
+}
+
+/**
+ * Move the playback to another output device.
+ *
+ * Every existing Sound, Music and ReverbEffect keeps playing where it was,
+ * only the output changes. This is typically used when the current device
+ * is unplugged.
+ *
+ * Requires the ALC_SOFT_reopen_device extension, if __ears__ isn't
+ * initialized yet it is initialized on the requested device.
+ *
+ * # Argument
+ * * `device_name` - The name of the device as returned by `get_devices`, or
+ * None to move to the default device.
+ *
+ * # Return
+ * `Ok(())` on success, `Err(OpenAlContextError)` otherwise, in which case the
+ * playback stays on the previous device.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device;
+ * fn main() -> Result<(), ears::OpenAlContextError> {
+ *     ears::init()?;
+ *     // Headphones were unplugged, go back to the default device
+ *     device::switch_device(None)?;
+ *     Ok(())
+ * }
+ * ```
+ */
+pub fn switch_device(device_name: Option<&str>) -> Result<(), OpenAlContextError> {
+    OpenAlData::reopen_device(device_name)
+}
+
+/**
+ * Check if the output device is still connected.
+ *
+ * Relies on the ALC_EXT_disconnect extension, without it the device is
+ * always reported as connected.
+ *
+ * # Return
+ * false if the device vanished (unplugged headphones for example), true
+ * otherwise.
+ */
+pub fn is_connected() -> bool {
+    OpenAlData::is_connected()
+}
+
+/**
+ * The receiving end of the connection events of the output device.
+ *
+ * Dereferences to the `Receiver` of the events, they stop being sent once
+ * it is dropped.
+ */
+pub struct DeviceEvents {
+    receiver: Receiver<DeviceEvent>,
+    /// Dropped with the receiver, telling the device monitor to forget it
+    _alive: Arc<()>,
+}
+
+impl Deref for DeviceEvents {
+    type Target = Receiver<DeviceEvent>;
+
+    fn deref(&self) -> &Receiver<DeviceEvent> {
+        &self.receiver
+    }
+}
+
+/**
+ * Get a channel receiving the connection events of the output device.
+ *
+ * The connection status is checked in the background a few times per
+ * second, an event is sent each time it changes.
+ *
+ * # Return
+ * The receiving end of the channel, the events stop being sent when it is
+ * dropped.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device::{self, DeviceEvent};
+ * let events = device::events();
+ * if let Ok(DeviceEvent::Disconnected) = events.try_recv() {
+ *     println!("The output device was disconnected");
+ *     device::switch_device(None).ok();
+ * }
+ * ```
+ */
+pub fn events() -> DeviceEvents {
+    let (sender, receiver) = channel();
+    let alive = Arc::new(());
+    let listener = Arc::downgrade(&alive);
+    OpenAlData::add_device_listener(Box::new(move |event| match event {
+        Some(event) => sender.send(event).is_ok(),
+        None => listener.upgrade().is_some(),
+    }));
+    DeviceEvents {
+        receiver: receiver,
+        _alive: alive,
+    }
+}
+
+/**
+ * Register a callback for the connection events of the output device.
+ *
+ * The callback is called from a background thread each time the connection
+ * status changes.
+ *
+ * # Argument
+ * * `callback` - The function to call with each event
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device::{self, DeviceEvent};
+ * device::on_event(|event| match event {
+ *     DeviceEvent::Disconnected => println!("Audio device lost"),
+ *     DeviceEvent::Reconnected => println!("Audio device back"),
+ * });
+ * ```
+ */
+pub fn on_event<F>(mut callback: F)
+where
+    F: FnMut(DeviceEvent) + Send + 'static,
+{
+    OpenAlData::add_device_listener(Box::new(move |event| {
+        if let Some(event) = event {
+            callback(event);
+        }
+        true
+    }));
+}
+
+/**
+ * Get the time of the output device clock.
+ *
+ * The clock counts the time played by the device since it was opened, it
+ * is the reference of `PlayGroup::play_at`. Relies on the
+ * ALC_SOFT_device_clock extension.
+ *
+ * # Return
+ * The device time, None if __ears__ isn't initialized or the extension is
+ * missing.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::device;
+ * if let Some(clock) = device::get_clock() {
+ *     println!("The device played for {:?}", clock);
+ * }
+ * ```
+ */
+pub fn get_clock() -> Option<Duration> {
+    let device = OpenAlData::device()?;
+    if !al::alcIsExtensionPresent(device, "ALC_SOFT_device_clock") {
+        return None;
+    }
+    let get_integer64: ffi::LPALCGETINTEGER64VSOFT =
+        match al::alcGetProcAddress(device, "alcGetInteger64vSOFT") {
+            Some(ptr) => unsafe { mem::transmute::<*mut c_void, ffi::LPALCGETINTEGER64VSOFT>(ptr) },
+            None => return None,
+        };
+
+    let mut clock: i64 = 0;
+    unsafe {
+        get_integer64(device, ffi::ALC_DEVICE_CLOCK_SOFT, 1, &mut clock);
+    }
+    Some(Duration::from_nanos(clock.max(0) as u64))
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use device::{
+        get_clock, get_current_device, get_default_device, get_devices, is_connected, switch_device,
+    };
+    use headless;
+    use init;
+    use std::time::Duration;
+
+    #[test]
+    #[ignore]
+    pub fn device_default_is_listed() -> () {
+        let devices = get_devices();
+        match get_default_device() {
+            Some(name) => assert!(devices.contains(&name)),
+            None => assert!(devices.is_empty()),
+        }
+    }
+
+    #[test]
+    #[ignore]
+    pub fn device_switch_to_default_OK() -> () {
+        init();
+        assert!(switch_device(None).is_ok());
+        assert!(get_current_device().is_some());
+    }
+
+    #[test]
+    pub fn device_is_connected_TRUE() -> () {
+        let _harness = headless::lock();
+
+        assert!(is_connected());
+    }
+
+    #[test]
+    pub fn device_get_clock_OK() -> () {
+        let mut harness = headless::lock();
+
+        // The loopback clock follows the rendered samples
+        if let Some(before) = get_clock() {
+            harness.render_short();
+            let after = get_clock().unwrap();
+            assert!(after - before >= Duration::from_millis(99));
+        }
+    }
+}
@@ -16,6 +16,12 @@ pub enum SoundError {
 
     /// Internal OpenAL error.
     InternalOpenALError(al::AlError),
+
+    /// The OpenAL extension needed by the operation is missing.
+    MissingExtension(String),
+
+    /// The loop points are not inside the samples or not ordered.
+    InvalidLoopPoints,
 }
 
 impl fmt::Display for SoundError {
@@ -28,6 +34,9 @@ impl fmt::Display for SoundError {
                 SoundError::LoadError(err) => format!("error while loading music file: {}", err),
                 SoundError::InvalidFormat => "unrecognized music format".to_string(),
                 SoundError::InternalOpenALError(err) => format!("internal OpenAL error: {}", err),
+                SoundError::MissingExtension(name) =>
+                    format!("the {} extension is not available", name),
+                SoundError::InvalidLoopPoints => "invalid loop points".to_string(),
             }
         )
     }
@@ -46,6 +55,8 @@ impl Error for SoundError {
             SoundError::LoadError(err) => Some(err),
             SoundError::InvalidFormat => None,
             SoundError::InternalOpenALError(err) => Some(err),
+            SoundError::MissingExtension(_) => None,
+            SoundError::InvalidLoopPoints => None,
         }
     }
 }
@@ -0,0 +1,187 @@
+//! Source parameters changed progressively by the streaming thread.
+
+use std::f32::consts::FRAC_PI_2;
+#[cfg(test)]
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use openal::al;
+
+/**
+ * The shape of a fade.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum FadeCurve {
+    /// The value changes at a constant rate.
+    #[default]
+    Linear,
+    /// The value follows a quarter of a sine, so two sources crossfading
+    /// keep the same perceived loudness.
+    EqualPower,
+}
+
+impl FadeCurve {
+    /// Get the value between from and to at progress, between 0 and 1.
+    fn value(&self, from: f32, to: f32, progress: f32) -> f32 {
+        let weight = match *self {
+            FadeCurve::Linear => progress,
+            // Rising fades follow the sine, falling ones the cosine
+            FadeCurve::EqualPower if to >= from => (progress * FRAC_PI_2).sin(),
+            FadeCurve::EqualPower => 1. - (progress * FRAC_PI_2).cos(),
+        };
+        from + (to - from) * weight
+    }
+}
+
+#[cfg(test)]
+lazy_static! {
+    static ref TEST_CLOCK: Mutex<Instant> = Mutex::new(Instant::now());
+}
+
+/// Get the time the ramps follow.
+#[cfg(not(test))]
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Get the time the ramps follow, standing still in the tests so the
+/// ramps only move with `advance_clock`, whatever the load of the machine.
+#[cfg(test)]
+pub fn now() -> Instant {
+    *lock_clock()
+}
+
+/// Move the time the ramps follow in the tests.
+#[cfg(test)]
+pub fn advance_clock(duration: Duration) -> () {
+    *lock_clock() += duration;
+}
+
+#[cfg(test)]
+fn lock_clock() -> MutexGuard<'static, Instant> {
+    match TEST_CLOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// A source parameter going from a value to another over time.
+pub struct Ramp {
+    source: u32,
+    param: i32,
+    from: f32,
+    to: f32,
+    start: Instant,
+    duration: Duration,
+    curve: FadeCurve,
+    /// Value set once the source is stopped at the end of the ramp
+    stop: Option<f32>,
+}
+
+impl Ramp {
+    /// Create a ramp of a float source parameter, starting now.
+    pub fn new(
+        source: u32,
+        param: i32,
+        from: f32,
+        to: f32,
+        duration: Duration,
+        curve: FadeCurve,
+    ) -> Ramp {
+        Ramp {
+            source: source,
+            param: param,
+            from: from,
+            to: to,
+            start: now(),
+            duration: duration,
+            curve: curve,
+            stop: None,
+        }
+    }
+
+    /// Stop the source once the ramp ends, the parameter then being set
+    /// to restore for the next play.
+    pub fn then_stop(mut self, restore: f32) -> Ramp {
+        self.stop = Some(restore);
+        self
+    }
+
+    pub fn source(&self) -> u32 {
+        self.source
+    }
+
+    pub fn param(&self) -> i32 {
+        self.param
+    }
+
+    /// Check if the source is stopped once the ramp ends.
+    pub fn stops(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    /// Get the value the parameter keeps once the ramp ended.
+    pub fn settled_value(&self) -> f32 {
+        self.stop.unwrap_or(self.to)
+    }
+
+    /// Abandon the ramp, setting the parameter to the value it would be
+    /// restored to if the ramp was meant to stop the source.
+    pub fn cancel(&self) -> () {
+        if let Some(restore) = self.stop {
+            al::alSourcef(self.source, self.param, restore);
+        }
+    }
+
+    /// Set the parameter to its value at this instant.
+    ///
+    /// Return false once the ramp ended.
+    pub fn apply(&self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.start);
+        let progress = if elapsed >= self.duration {
+            1.
+        } else {
+            elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        };
+
+        al::alSourcef(
+            self.source,
+            self.param,
+            self.curve.value(self.from, self.to, progress),
+        );
+        if progress < 1. {
+            return true;
+        }
+
+        if let Some(restore) = self.stop {
+            al::alSourceStop(self.source);
+            al::alSourcef(self.source, self.param, restore);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fade::FadeCurve;
+
+    #[test]
+    fn fade_curve_linear() -> () {
+        assert_eq!(FadeCurve::Linear.value(0., 1., 0.25), 0.25);
+        assert_eq!(FadeCurve::Linear.value(1., 0., 0.25), 0.75);
+        assert_eq!(FadeCurve::Linear.value(0.5, 1., 1.), 1.);
+    }
+
+    #[test]
+    fn fade_curve_equal_power() -> () {
+        // The gains of a crossfade keep the same total power
+        for step in 0..=10 {
+            let progress = step as f32 / 10.;
+            let fade_out = FadeCurve::EqualPower.value(1., 0., progress);
+            let fade_in = FadeCurve::EqualPower.value(0., 1., progress);
+            assert!((fade_out * fade_out + fade_in * fade_in - 1.).abs() < 1e-5);
+        }
+        assert_eq!(FadeCurve::EqualPower.value(0., 1., 1.), 1.);
+        assert!(FadeCurve::EqualPower.value(1., 0., 1.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,209 @@
+//! Headless audio context for the test suite.
+//!
+//! The tests don't need a sound card: the first one calling `lock` creates
+//! a Loopback device as the ears context, and the scene is then rendered on
+//! demand so the tests can check what would have been heard.
+//!
+//! Every test using OpenAL must hold the harness for its whole duration,
+//! the audio scene and the listener are shared by all the tests.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use compression;
+use compression::Compression;
+use listener;
+use loopback::Loopback;
+use openal::al;
+use sample_format;
+use sample_format::SampleFormat;
+
+/// Frequency of the headless context.
+pub const SAMPLE_RATE: i32 = 44100;
+
+/// Number of channels of the headless context.
+pub const CHANNELS: i32 = 2;
+
+/// Number of temporary files created, to name them.
+static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref HARNESS: Mutex<Harness> = Mutex::new(Harness::new());
+}
+
+/// The headless context, locked by the running test.
+pub struct Harness {
+    loopback: Loopback,
+}
+
+// The loopback device is only used while the harness is locked.
+unsafe impl Send for Harness {}
+
+impl Harness {
+    fn new() -> Harness {
+        Harness {
+            loopback: Loopback::new(SAMPLE_RATE, CHANNELS)
+                .expect("Cannot create the headless loopback device"),
+        }
+    }
+
+    /// Render the next part of the scene.
+    pub fn render(&mut self, duration: Duration) -> Vec<i16> {
+        self.loopback.render_duration(duration)
+    }
+
+    /// Render the next 100 milliseconds of the scene.
+    pub fn render_short(&mut self) -> Vec<i16> {
+        self.render(Duration::from_millis(100))
+    }
+}
+
+/**
+ * Lock the headless context for the calling test.
+ *
+ * The listener, the sample format and the compression are reset to their
+ * default state and pending OpenAL errors are cleared, so a test doesn't
+ * depend on what ran before it.
+ */
+pub fn lock() -> MutexGuard<'static, Harness> {
+    // A failing test poisons the mutex, the context itself is still usable
+    let harness = match HARNESS.lock() {
+        Ok(harness) => harness,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    listener::set_volume(1.);
+    listener::set_position([0., 0., 0.]);
+    listener::set_orientation([0., 0., -1.], [0., 1., 0.]);
+    listener::set_velocity([0., 0., 0.]);
+    sample_format::set_sample_format(SampleFormat::Auto);
+    compression::set_compression(Compression::Uncompressed);
+    al::openal_has_error();
+
+    harness
+}
+
+/// Get the highest absolute value of the rendered samples.
+pub fn peak(samples: &[i16]) -> i32 {
+    samples
+        .iter()
+        .map(|sample| (*sample as i32).abs())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Check if the rendered samples are silent.
+pub fn is_silent(samples: &[i16]) -> bool {
+    peak(samples) == 0
+}
+
+fn u32_le(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8,
+    ]);
+}
+
+/// Create a 16 bit mono WAV file at the headless frequency, with extra
+/// chunks before the samples.
+fn wav_with_chunks(samples: &[i16], chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let chunks_size: u32 = chunks
+        .iter()
+        .map(|&(_, chunk)| 8 + chunk.len() as u32)
+        .sum();
+
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&[1, 0, 1, 0]);
+    u32_le(&mut fmt, SAMPLE_RATE as u32);
+    u32_le(&mut fmt, SAMPLE_RATE as u32 * 2);
+    fmt.extend_from_slice(&[2, 0, 16, 0]);
+
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    u32_le(&mut wav, 4 + 8 + 16 + chunks_size + 8 + data_size);
+    wav.extend_from_slice(b"WAVEfmt ");
+    u32_le(&mut wav, 16);
+    wav.extend_from_slice(&fmt);
+    for &(id, chunk) in chunks {
+        wav.extend_from_slice(id);
+        u32_le(&mut wav, chunk.len() as u32);
+        wav.extend_from_slice(chunk);
+    }
+    wav.extend_from_slice(b"data");
+    u32_le(&mut wav, data_size);
+    for sample in samples {
+        wav.extend_from_slice(&[*sample as u8, (*sample >> 8) as u8]);
+    }
+    wav
+}
+
+/// Create a plain 16 bit mono WAV file at the headless frequency.
+pub fn wav(samples: &[i16]) -> Vec<u8> {
+    wav_with_chunks(samples, &[])
+}
+
+/// Create a 16 bit mono WAV file at the headless frequency, with a smpl
+/// chunk looping over [start, end), the chunk holding the last frame played.
+pub fn wav_with_loop(samples: &[i16], start: u32, end: u32) -> Vec<u8> {
+    let mut smpl = Vec::new();
+    for value in &[0, 0, 22675, 60, 0, 0, 0, 1, 0, 0, 0, start, end - 1, 0, 0] {
+        u32_le(&mut smpl, *value);
+    }
+    wav_with_chunks(samples, &[(b"smpl", &smpl)])
+}
+
+/// Create a 16 bit mono WAV file at the headless frequency, with a cue
+/// chunk holding the (identifier, frame) cue points.
+pub fn wav_with_cues(samples: &[i16], cues: &[(u32, u32)]) -> Vec<u8> {
+    let mut cue = Vec::new();
+    u32_le(&mut cue, cues.len() as u32);
+    for &(id, frame) in cues {
+        u32_le(&mut cue, id);
+        u32_le(&mut cue, frame);
+        cue.extend_from_slice(b"data");
+        u32_le(&mut cue, 0);
+        u32_le(&mut cue, 0);
+        u32_le(&mut cue, frame);
+    }
+    wav_with_chunks(samples, &[(b"cue ", &cue)])
+}
+
+/// A WAV file in the temporary directory, deleted when dropped.
+///
+/// For the APIs taking a path, the other tests use the bytes of `wav`.
+pub struct TempWav {
+    path: PathBuf,
+}
+
+impl TempWav {
+    /// Write a plain 16 bit mono WAV file under a name unique to the
+    /// process, so concurrent test runs don't collide.
+    pub fn new(samples: &[i16]) -> TempWav {
+        let path = env::temp_dir().join(format!(
+            "ears_test_{}_{}.wav",
+            process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, wav(samples)).expect("Cannot write file");
+        TempWav { path: path }
+    }
+
+    /// Get the path of the file.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+
+impl Drop for TempWav {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,208 @@
+//! Module to control the HRTF (binaural) rendering.
+//!
+//! HRTF gives a convincing 3D positioning of the sounds when playing on
+//! headphones. It's provided by the ALC_SOFT_HRTF extension of OpenAL Soft,
+//! every function of this module fails gracefully if the extension is
+//! missing.
+
+use internal::{OpenAlContextError, OpenAlData};
+use openal::{al, ffi};
+
+/// The HRTF configurations which can be requested.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Hrtf {
+    /// Let the implementation decide, usually enabled on headphones only
+    Auto,
+    /// Force HRTF rendering on
+    Enabled,
+    /// Force HRTF rendering off
+    Disabled,
+    /// Force HRTF rendering on with the dataset of the given name, as
+    /// returned by `get_hrtfs`
+    Dataset(String),
+}
+
+/// The status of the HRTF rendering.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum HrtfStatus {
+    /// HRTF is disabled
+    Disabled,
+    /// HRTF is enabled
+    Enabled,
+    /// HRTF is disabled because it's not allowed on the device
+    Denied,
+    /// HRTF is enabled because it must be used on the device
+    Required,
+    /// HRTF is enabled because the device reported headphones
+    HeadphonesDetected,
+    /// HRTF is disabled because the device doesn't support it with the
+    /// current format
+    UnsupportedFormat,
+}
+
+/**
+ * Get the names of the available HRTF datasets.
+ *
+ * # Return
+ * The names of the datasets, empty if the ALC_SOFT_HRTF extension isn't
+ * available.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::hrtf;
+ * for name in hrtf::get_hrtfs() {
+ *     println!("HRTF dataset: {}", name);
+ * }
+ * ```
+ */
+pub fn get_hrtfs() -> Vec<String> {
+    let device = match OpenAlData::check_al_context()
+        .ok()
+        .and(OpenAlData::device())
+    {
+        Some(device) => device,
+        None => return Vec::new(),
+    };
+    if !al::alcIsExtensionPresent(device, "ALC_SOFT_HRTF") {
+        return Vec::new();
+    }
+
+    let mut count = 0;
+    unsafe { ffi::alcGetIntegerv(device, ffi::ALC_NUM_HRTF_SPECIFIERS_SOFT, 1, &mut count) };
+    (0..count)
+        .filter_map(|i| OpenAlData::get_string_indexed(ffi::ALC_HRTF_SPECIFIER_SOFT, i))
+        .collect()
+}
+
+/**
+ * Request a HRTF configuration.
+ *
+ * The device is reset with the new configuration, Sounds and Musics keep
+ * playing. Check `get_status` afterwards to know if the request was
+ * honored, the device may deny it.
+ *
+ * # Argument
+ * * `hrtf` - The requested configuration
+ *
+ * # Return
+ * `Ok(())` if the device was reset, `Err(OpenAlContextError)` if the
+ * extension is missing, the dataset is unknown or the reset failed.
+ *
+ * # Example
+ * ```no_run
+ * # use ears::hrtf::{self, Hrtf, HrtfStatus};
+ * fn main() -> Result<(), ears::OpenAlContextError> {
+ *     hrtf::set_hrtf(Hrtf::Enabled)?;
+ *     if hrtf::get_status() != HrtfStatus::Enabled {
+ *         println!("HRTF could not be enabled");
+ *     }
+ *     Ok(())
+ * }
+ * ```
+ */
+pub fn set_hrtf(hrtf: Hrtf) -> Result<(), OpenAlContextError> {
+    let attributes = get_attributes(&hrtf)?;
+    OpenAlData::reset_device(&attributes)
+}
+
+/// Get the context attributes requesting a HRTF configuration.
+///
+/// A dataset can only be resolved once the device is opened.
+#[doc(hidden)]
+pub fn get_attributes(hrtf: &Hrtf) -> Result<Vec<i32>, OpenAlContextError> {
+    match *hrtf {
+        Hrtf::Auto => Ok(vec![ffi::ALC_HRTF_SOFT, ffi::ALC_DONT_CARE_SOFT]),
+        Hrtf::Enabled => Ok(vec![ffi::ALC_HRTF_SOFT, ffi::ALC_TRUE as i32]),
+        Hrtf::Disabled => Ok(vec![ffi::ALC_HRTF_SOFT, ffi::ALC_FALSE as i32]),
+        Hrtf::Dataset(ref name) => match get_hrtfs().iter().position(|dataset| dataset == name) {
+            Some(index) => Ok(vec![
+                ffi::ALC_HRTF_SOFT,
+                ffi::ALC_TRUE as i32,
+                ffi::ALC_HRTF_ID_SOFT,
+                index as i32,
+            ]),
+            None => Err(OpenAlContextError::UnknownHrtf(name.clone())),
+        },
+    }
+}
+
+/**
+ * Get the status of the HRTF rendering.
+ *
+ * # Return
+ * The status as a variant of HrtfStatus, always `Disabled` if the
+ * ALC_SOFT_HRTF extension isn't available.
+ */
+pub fn get_status() -> HrtfStatus {
+    let device = match OpenAlData::check_al_context()
+        .ok()
+        .and(OpenAlData::device())
+    {
+        Some(device) => device,
+        None => return HrtfStatus::Disabled,
+    };
+    if !al::alcIsExtensionPresent(device, "ALC_SOFT_HRTF") {
+        return HrtfStatus::Disabled;
+    }
+
+    let mut status = ffi::ALC_HRTF_DISABLED_SOFT;
+    unsafe { ffi::alcGetIntegerv(device, ffi::ALC_HRTF_STATUS_SOFT, 1, &mut status) };
+    match status {
+        ffi::ALC_HRTF_ENABLED_SOFT => HrtfStatus::Enabled,
+        ffi::ALC_HRTF_DENIED_SOFT => HrtfStatus::Denied,
+        ffi::ALC_HRTF_REQUIRED_SOFT => HrtfStatus::Required,
+        ffi::ALC_HRTF_HEADPHONES_DETECTED_SOFT => HrtfStatus::HeadphonesDetected,
+        ffi::ALC_HRTF_UNSUPPORTED_FORMAT_SOFT => HrtfStatus::UnsupportedFormat,
+        _ => HrtfStatus::Disabled,
+    }
+}
+
+/**
+ * Check if the HRTF rendering is active.
+ *
+ * # Return
+ * true if the status is `Enabled`, `Required` or `HeadphonesDetected`.
+ */
+pub fn is_enabled() -> bool {
+    matches!(
+        get_status(),
+        HrtfStatus::Enabled | HrtfStatus::Required | HrtfStatus::HeadphonesDetected
+    )
+}
+
+/**
+ * Get the name of the HRTF dataset in use.
+ *
+ * # Return
+ * The name of the dataset, None if HRTF isn't active.
+ */
+pub fn get_current_hrtf() -> Option<String> {
+    if !is_enabled() {
+        return None;
+    }
+    al::alcGetString(OpenAlData::device()?, ffi::ALC_HRTF_SPECIFIER_SOFT)
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use headless;
+    use hrtf::{get_current_hrtf, get_status, set_hrtf, Hrtf, HrtfStatus};
+
+    #[test]
+    fn hrtf_disable_OK() -> () {
+        let _harness = headless::lock();
+
+        assert!(set_hrtf(Hrtf::Disabled).is_ok());
+        assert_eq!(get_status(), HrtfStatus::Disabled);
+        assert_eq!(get_current_hrtf(), None);
+    }
+
+    #[test]
+    fn hrtf_unknown_dataset_FAIL() -> () {
+        let _harness = headless::lock();
+
+        assert!(set_hrtf(Hrtf::Dataset("ears unknown dataset".to_string())).is_err());
+    }
+}
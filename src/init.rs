@@ -49,6 +49,33 @@ pub fn init() -> Result<(), OpenAlContextError> {
     return OpenAlData::check_al_context();
 }
 
+/**
+ * Initialize the internal context on a specific output device
+ *
+ * Must be called before any other use of __ears__, otherwise the context is
+ * already created on the default device.
+ *
+ * # Argument
+ * * `device_name` - The name of the device, as returned by
+ * `ears::device::get_devices()`
+ *
+ * # Return
+ * `Ok(())` if initialization is successful, `Err(OpenAlContextError)` otherwise
+ *
+ * # Example
+ * ```no_run
+ * fn main() -> Result<(), ears::OpenAlContextError> {
+ *     if let Some(name) = ears::device::get_devices().last() {
+ *         ears::init_with_device(name)?;
+ *     }
+ *     Ok(())
+ * }
+ * ```
+ */
+pub fn init_with_device(device_name: &str) -> Result<(), OpenAlContextError> {
+    OpenAlData::init_with(Some(device_name), Vec::new())
+}
+
 /**
  * Initialize the input device context
  *
@@ -71,16 +98,25 @@ pub fn init_in() -> Result<RecordContext, OpenAlContextError> {
 mod test {
     #![allow(non_snake_case)]
 
+    use headless;
     use init;
     use init_in;
+    use init_with_device;
     use std::thread;
 
     #[test]
-    #[ignore]
     fn test_init_ears_OK() -> () {
+        let _harness = headless::lock();
+
         assert!(init().is_ok())
     }
 
+    #[test]
+    #[ignore]
+    fn test_init_with_unknown_device_FAIL() -> () {
+        assert!(init_with_device("ears unknown device").is_err())
+    }
+
     #[test]
     #[ignore]
     fn test_init_in_with_normal_init_OK() -> () {
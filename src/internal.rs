@@ -26,20 +26,31 @@
 
 #![macro_use]
 
+use device::DeviceEvent;
 use libc;
-use openal::ffi;
+use libc::c_void;
+use openal::{al, ffi};
 use record_context;
 use record_context::RecordContext;
 use std::cell::RefCell;
 use std::error::Error;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::fmt;
+use std::mem;
 use std::ptr;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub enum OpenAlContextError {
     DefaultDeviceError,
+    DeviceError(String),
+    AlreadyInitialized,
+    MissingExtension(String),
+    UnknownHrtf(String),
+    UnsupportedRenderFormat,
     CreationError,
     MakeCurrentError,
     NoInputDevice,
@@ -56,6 +67,16 @@ impl fmt::Display for OpenAlContextError {
             match self {
                 OpenAlContextError::DefaultDeviceError =>
                     "cannot open the default device".to_string(),
+                OpenAlContextError::DeviceError(name) =>
+                    format!("cannot open the device \"{}\"", name),
+                OpenAlContextError::AlreadyInitialized =>
+                    "the OpenAL context is already initialized".to_string(),
+                OpenAlContextError::MissingExtension(name) =>
+                    format!("the {} extension is not available", name),
+                OpenAlContextError::UnsupportedRenderFormat =>
+                    "the loopback device doesn't support this render format".to_string(),
+                OpenAlContextError::UnknownHrtf(name) =>
+                    format!("unknown HRTF dataset \"{}\"", name),
                 OpenAlContextError::CreationError => "cannot create the OpenAL context".to_string(),
                 OpenAlContextError::MakeCurrentError =>
                     "cannot make the OpenAL context current".to_string(),
@@ -82,8 +103,26 @@ impl fmt::Debug for OpenAlContextError {
 impl Error for OpenAlContextError {}
 
 lazy_static! {
-    static ref AL_CONTEXT: Mutex<Result<OpenAlData, OpenAlContextError>> =
-        Mutex::new(OpenAlData::new());
+    static ref AL_CONTEXT: Mutex<Option<Result<OpenAlData, OpenAlContextError>>> = Mutex::new(None);
+    static ref DEVICE_MONITOR: Mutex<DeviceMonitor> = Mutex::new(DeviceMonitor {
+        listeners: Vec::new(),
+        running: false,
+    });
+}
+
+/// Interval at which the device monitor polls the connection status.
+const DEVICE_POLL_INTERVAL: u64 = 250;
+
+/// A device listener returns false once it doesn't want events anymore.
+///
+/// It is called with None on each poll, to check if it is still wanted.
+type DeviceListener = Box<dyn FnMut(Option<DeviceEvent>) -> bool + Send>;
+
+/// The listeners of the device monitor thread.
+struct DeviceMonitor {
+    listeners: Vec<DeviceListener>,
+    /// Whether the monitor thread is running
+    running: bool,
 }
 
 #[derive(Clone)]
@@ -91,19 +130,51 @@ pub struct OpenAlData {
     pub al_context: ffi::ALCcontextPtr,
     pub al_device: ffi::ALCdevicePtr,
     pub al_capt_device: ffi::ALCdevicePtr,
+    /// The zero terminated attribute list the context was created with
+    pub attributes: Vec<i32>,
 }
 
 impl OpenAlData {
     /// Create a new OpenAlData struct
     ///
+    /// Open the device named `device_name`, or the default device if None,
+    /// and create the context with the given attributes.
+    ///
     /// Private method.
-    fn new() -> Result<OpenAlData, OpenAlContextError> {
-        let device = unsafe { ffi::alcOpenDevice(ptr::null_mut()) };
+    fn new(
+        device_name: Option<&str>,
+        attributes: Vec<i32>,
+    ) -> Result<OpenAlData, OpenAlContextError> {
+        let device = match device_name {
+            Some(name) => {
+                let c_str = match CString::new(name) {
+                    Ok(c_str) => c_str,
+                    Err(_) => return Err(OpenAlContextError::DeviceError(name.to_string())),
+                };
+                unsafe { ffi::alcOpenDevice(c_str.as_ptr() as *mut _) }
+            }
+            None => unsafe { ffi::alcOpenDevice(ptr::null_mut()) },
+        };
         if device == 0 {
-            return Err(OpenAlContextError::DefaultDeviceError);
+            return Err(match device_name {
+                Some(name) => OpenAlContextError::DeviceError(name.to_string()),
+                None => OpenAlContextError::DefaultDeviceError,
+            });
         }
-        let context = unsafe { ffi::alcCreateContext(device, ptr::null_mut()) };
+        OpenAlData::create_context(device, attributes)
+    }
+
+    /// Create the context on an opened device and make it current.
+    ///
+    /// Private method.
+    fn create_context(
+        device: ffi::ALCdevicePtr,
+        mut attributes: Vec<i32>,
+    ) -> Result<OpenAlData, OpenAlContextError> {
+        attributes.push(0);
+        let context = unsafe { ffi::alcCreateContext(device, attributes.as_mut_ptr()) };
         if context == 0 {
+            unsafe { ffi::alcCloseDevice(device) };
             return Err(OpenAlContextError::CreationError);
         }
         if unsafe { ffi::alcMakeContextCurrent(context) } == ffi::ALC_FALSE {
@@ -118,6 +189,7 @@ impl OpenAlData {
             al_context: context,
             al_device: device,
             al_capt_device: 0,
+            attributes: attributes,
         })
     }
 
@@ -134,19 +206,366 @@ impl OpenAlData {
         if unsafe { ffi::alcGetCurrentContext() != 0 } {
             return Ok(());
         }
+        match AL_CONTEXT.lock() {
+            Ok(mut guard) => {
+                if guard.is_none() {
+                    *guard = Some(OpenAlData::new(None, Vec::new()));
+                }
+                match *guard {
+                    Some(Ok(_)) => Ok(()),
+                    Some(Err(ref err)) => Err(err.clone()),
+                    None => unreachable!(),
+                }
+            }
+            Err(poison_error) => Err(OpenAlContextError::LockError(poison_error.to_string())),
+        }
+    }
+
+    /// Create the context on a specific device with specific attributes.
+    ///
+    /// Fails if the context was already successfully created, a previous
+    /// failure can be retried with another device.
+    ///
+    /// # Arguments
+    /// * `device_name` - The name of the device as returned by the device
+    /// enumeration, or None for the default device.
+    /// * `attributes` - The attribute list passed to alcCreateContext, as
+    /// key/value pairs without the terminating 0.
+    pub fn init_with(
+        device_name: Option<&str>,
+        attributes: Vec<i32>,
+    ) -> Result<(), OpenAlContextError> {
+        match AL_CONTEXT.lock() {
+            Ok(mut guard) => {
+                if let Some(Ok(_)) = *guard {
+                    return Err(OpenAlContextError::AlreadyInitialized);
+                }
+                match OpenAlData::new(device_name, attributes) {
+                    Ok(data) => {
+                        *guard = Some(Ok(data));
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            Err(poison_error) => Err(OpenAlContextError::LockError(poison_error.to_string())),
+        }
+    }
+
+    /// Create the context on a loopback device.
+    ///
+    /// Uses the ALC_SOFT_loopback extension, the mix isn't sent to any
+    /// output but rendered on demand by the application.
+    ///
+    /// # Arguments
+    /// * `sample_rate` - The frequency of the rendered samples
+    /// * `channels` - One of the ALC_*_SOFT channel configurations
+    /// * `sample_type` - One of the ALC_*_SOFT sample types
+    ///
+    /// # Return
+    /// The loopback device on success.
+    pub fn init_loopback(
+        sample_rate: i32,
+        channels: i32,
+        sample_type: i32,
+    ) -> Result<ffi::ALCdevicePtr, OpenAlContextError> {
+        let missing = || OpenAlContextError::MissingExtension("ALC_SOFT_loopback".to_string());
+        if !al::alcIsExtensionPresent(0, "ALC_SOFT_loopback") {
+            return Err(missing());
+        }
+        let open: ffi::LPALCLOOPBACKOPENDEVICESOFT =
+            match al::alcGetProcAddress(0, "alcLoopbackOpenDeviceSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALCLOOPBACKOPENDEVICESOFT>(ptr)
+                },
+                None => return Err(missing()),
+            };
+        let is_supported: ffi::LPALCISRENDERFORMATSUPPORTEDSOFT =
+            match al::alcGetProcAddress(0, "alcIsRenderFormatSupportedSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALCISRENDERFORMATSUPPORTEDSOFT>(ptr)
+                },
+                None => return Err(missing()),
+            };
+
+        let mut guard = match AL_CONTEXT.lock() {
+            Ok(guard) => guard,
+            Err(poison_error) => {
+                return Err(OpenAlContextError::LockError(poison_error.to_string()))
+            }
+        };
+        if let Some(Ok(_)) = *guard {
+            return Err(OpenAlContextError::AlreadyInitialized);
+        }
+
+        let device = unsafe { open(ptr::null()) };
+        if device == 0 {
+            return Err(OpenAlContextError::DeviceError("loopback".to_string()));
+        }
+        if unsafe { is_supported(device, sample_rate, channels, sample_type) } == ffi::ALC_FALSE {
+            unsafe { ffi::alcCloseDevice(device) };
+            return Err(OpenAlContextError::UnsupportedRenderFormat);
+        }
+
+        let attributes = vec![
+            ffi::ALC_FREQUENCY,
+            sample_rate,
+            ffi::ALC_FORMAT_CHANNELS_SOFT,
+            channels,
+            ffi::ALC_FORMAT_TYPE_SOFT,
+            sample_type,
+        ];
+        let data = OpenAlData::create_context(device, attributes)?;
+        *guard = Some(Ok(data));
+        Ok(device)
+    }
+
+    /// Move the context to another device.
+    ///
+    /// Uses the ALC_SOFT_reopen_device extension so the context, and every
+    /// source, buffer and effect slot it contains, is kept as is. If the
+    /// context isn't created yet it is simply created on the device.
+    ///
+    /// # Argument
+    /// * `device_name` - The name of the new device, or None for the default
+    /// device.
+    pub fn reopen_device(device_name: Option<&str>) -> Result<(), OpenAlContextError> {
+        let guard = match AL_CONTEXT.lock() {
+            Ok(guard) => guard,
+            Err(poison_error) => {
+                return Err(OpenAlContextError::LockError(poison_error.to_string()))
+            }
+        };
+        let (device, attributes) = match *guard {
+            Some(Ok(ref data)) => (data.al_device, data.attributes.clone()),
+            _ => {
+                drop(guard);
+                return OpenAlData::init_with(device_name, Vec::new());
+            }
+        };
+
+        if !al::alcIsExtensionPresent(device, "ALC_SOFT_reopen_device") {
+            return Err(OpenAlContextError::MissingExtension(
+                "ALC_SOFT_reopen_device".to_string(),
+            ));
+        }
+        let reopen: ffi::LPALCREOPENDEVICESOFT =
+            match al::alcGetProcAddress(device, "alcReopenDeviceSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALCREOPENDEVICESOFT>(ptr)
+                },
+                None => {
+                    return Err(OpenAlContextError::MissingExtension(
+                        "ALC_SOFT_reopen_device".to_string(),
+                    ))
+                }
+            };
+
+        let c_str = match device_name {
+            Some(name) => match CString::new(name) {
+                Ok(c_str) => Some(c_str),
+                Err(_) => return Err(OpenAlContextError::DeviceError(name.to_string())),
+            },
+            None => None,
+        };
+        let name_ptr = match c_str {
+            Some(ref c_str) => c_str.as_ptr(),
+            None => ptr::null(),
+        };
+
+        if unsafe { reopen(device, name_ptr, attributes.as_ptr()) } == ffi::ALC_FALSE {
+            return Err(match device_name {
+                Some(name) => OpenAlContextError::DeviceError(name.to_string()),
+                None => OpenAlContextError::DefaultDeviceError,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reset the device with updated attributes.
+    ///
+    /// Uses the ALC_SOFT_HRTF extension, the given key/value pairs replace
+    /// the ones the context was created with, the other attributes are kept.
+    ///
+    /// # Argument
+    /// * `update` - The attributes to change, as key/value pairs without the
+    /// terminating 0.
+    pub fn reset_device(update: &[i32]) -> Result<(), OpenAlContextError> {
+        OpenAlData::check_al_context()?;
+        let mut guard = match AL_CONTEXT.lock() {
+            Ok(guard) => guard,
+            Err(poison_error) => {
+                return Err(OpenAlContextError::LockError(poison_error.to_string()))
+            }
+        };
+        let data = match *guard {
+            Some(Ok(ref mut data)) => data,
+            _ => return Err(OpenAlContextError::CreationError),
+        };
+
+        if !al::alcIsExtensionPresent(data.al_device, "ALC_SOFT_HRTF") {
+            return Err(OpenAlContextError::MissingExtension(
+                "ALC_SOFT_HRTF".to_string(),
+            ));
+        }
+        let reset: ffi::LPALCRESETDEVICESOFT =
+            match al::alcGetProcAddress(data.al_device, "alcResetDeviceSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALCRESETDEVICESOFT>(ptr)
+                },
+                None => {
+                    return Err(OpenAlContextError::MissingExtension(
+                        "ALC_SOFT_HRTF".to_string(),
+                    ))
+                }
+            };
+
+        let attributes = merge_attributes(&data.attributes, update);
+        if unsafe { reset(data.al_device, attributes.as_ptr()) } == ffi::ALC_FALSE {
+            return Err(OpenAlContextError::CreationError);
+        }
+        data.attributes = attributes;
+        Ok(())
+    }
+
+    /// Query an indexed ALC string of the ALC_SOFT_HRTF extension.
+    ///
+    /// # Return
+    /// None if the context isn't created, the extension isn't present or
+    /// the index is out of range.
+    pub fn get_string_indexed(param: i32, index: i32) -> Option<String> {
+        OpenAlData::check_al_context().ok()?;
+        let device = OpenAlData::device()?;
+        if !al::alcIsExtensionPresent(device, "ALC_SOFT_HRTF") {
+            return None;
+        }
+        let proc_address = al::alcGetProcAddress(device, "alcGetStringiSOFT")?;
+        let get_string =
+            unsafe { mem::transmute::<*mut c_void, ffi::LPALCGETSTRINGISOFT>(proc_address) };
+        let ptr = unsafe { get_string(device, param, index) };
+        if ptr.is_null() {
+            None
+        } else {
+            let c_str = unsafe { CStr::from_ptr(ptr) };
+            Some(c_str.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Get the output device handle, None if the context isn't created.
+    pub fn device() -> Option<ffi::ALCdevicePtr> {
         match AL_CONTEXT.lock() {
             Ok(guard) => match *guard {
-                Ok(_) => Ok(()),
-                Err(ref err) => Err(err.clone()),
+                Some(Ok(ref data)) => Some(data.al_device),
+                _ => None,
             },
-            Err(poison_error) => Err(OpenAlContextError::LockError(poison_error.to_string())),
+            Err(_) => None,
+        }
+    }
+
+    /// Get the attributes granted by the implementation.
+    ///
+    /// Reads ALC_ALL_ATTRIBUTES, which may differ from the attributes
+    /// requested when creating the context.
+    ///
+    /// # Return
+    /// The attributes as key/value pairs, empty if the context isn't created.
+    pub fn granted_attributes() -> Vec<(i32, i32)> {
+        match AL_CONTEXT.lock() {
+            Ok(guard) => match *guard {
+                Some(Ok(ref data)) => {
+                    let mut size = 0;
+                    unsafe {
+                        ffi::alcGetIntegerv(data.al_device, ffi::ALC_ATTRIBUTES_SIZE, 1, &mut size)
+                    };
+                    if size <= 0 {
+                        return Vec::new();
+                    }
+                    let mut attributes = vec![0; size as usize];
+                    unsafe {
+                        ffi::alcGetIntegerv(
+                            data.al_device,
+                            ffi::ALC_ALL_ATTRIBUTES,
+                            size,
+                            attributes.as_mut_ptr(),
+                        )
+                    };
+                    attributes
+                        .chunks(2)
+                        .take_while(|pair| pair.len() == 2 && pair[0] != 0)
+                        .map(|pair| (pair[0], pair[1]))
+                        .collect()
+                }
+                _ => Vec::new(),
+            },
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Check if the output device is still connected.
+    ///
+    /// Uses the ALC_EXT_disconnect extension, without it the device is
+    /// always considered as connected.
+    ///
+    /// # Return
+    /// true if the device is connected or the context isn't created yet,
+    /// false otherwise.
+    pub fn is_connected() -> bool {
+        match AL_CONTEXT.lock() {
+            Ok(guard) => match *guard {
+                Some(Ok(ref data)) => {
+                    if !al::alcIsExtensionPresent(data.al_device, "ALC_EXT_disconnect") {
+                        return true;
+                    }
+                    let mut connected = 1;
+                    unsafe {
+                        ffi::alcGetIntegerv(data.al_device, ffi::ALC_CONNECTED, 1, &mut connected)
+                    };
+                    connected != 0
+                }
+                _ => true,
+            },
+            Err(_) => true,
+        }
+    }
+
+    /// Register a listener for the device connection events.
+    ///
+    /// The listeners are called from the device monitor thread, which is
+    /// started with the first listener.
+    pub fn add_device_listener(listener: DeviceListener) {
+        let mut monitor = lock_device_monitor();
+        monitor.listeners.push(listener);
+        if !monitor.running {
+            monitor.running = true;
+            start_device_monitor();
+        }
+    }
+
+    /// Get the name of the device the context was created on.
+    ///
+    /// # Return
+    /// None if the context isn't created yet.
+    pub fn current_device_name() -> Option<String> {
+        match AL_CONTEXT.lock() {
+            Ok(guard) => match *guard {
+                Some(Ok(ref data)) => {
+                    let param = if al::alcIsExtensionPresent(0, "ALC_ENUMERATE_ALL_EXT") {
+                        ffi::ALC_ALL_DEVICES_SPECIFIER
+                    } else {
+                        ffi::ALC_DEVICE_SPECIFIER
+                    };
+                    al::alcGetString(data.al_device, param)
+                }
+                _ => None,
+            },
+            Err(_) => None,
         }
     }
 
     fn is_input_context_init() -> Result<RecordContext, OpenAlContextError> {
         match AL_CONTEXT.lock() {
             Ok(mut guard) => {
-                if let Ok(ref mut new_context) = *guard {
+                if let Some(Ok(ref mut new_context)) = *guard {
                     if new_context.al_capt_device != 0 {
                         Ok(record_context::new(new_context.al_capt_device))
                     } else {
@@ -211,9 +630,70 @@ impl OpenAlData {
     }
 }
 
+// Replace the key/value pairs of the zero terminated attribute list `list`
+// by the ones in `update`, returning a new zero terminated list.
+fn merge_attributes(list: &[i32], update: &[i32]) -> Vec<i32> {
+    let mut merged: Vec<i32> = Vec::new();
+    for pair in list
+        .chunks(2)
+        .take_while(|pair| pair.len() == 2 && pair[0] != 0)
+    {
+        if !update.chunks(2).any(|new_pair| new_pair[0] == pair[0]) {
+            merged.extend_from_slice(pair);
+        }
+    }
+    merged.extend_from_slice(update);
+    merged.push(0);
+    merged
+}
+
+fn lock_device_monitor() -> MutexGuard<'static, DeviceMonitor> {
+    match DEVICE_MONITOR.lock() {
+        Ok(monitor) => monitor,
+        Err(poison_error) => poison_error.into_inner(),
+    }
+}
+
+// Poll the connection status of the device and notify the listeners each
+// time it changes. The thread stops when there is no listener left.
+//
+// The listeners are called without the lock held, so they can register new
+// listeners.
+fn start_device_monitor() {
+    let thread = thread::Builder::new().name(String::from("ears-device-monitor"));
+    thread
+        .spawn(move || {
+            let mut was_connected = OpenAlData::is_connected();
+            loop {
+                sleep(Duration::from_millis(DEVICE_POLL_INTERVAL));
+
+                let connected = OpenAlData::is_connected();
+                let event = match connected == was_connected {
+                    true => None,
+                    false if connected => Some(DeviceEvent::Reconnected),
+                    false => Some(DeviceEvent::Disconnected),
+                };
+                was_connected = connected;
+
+                let mut listeners = mem::take(&mut lock_device_monitor().listeners);
+                listeners.retain_mut(|listener| listener(event));
+
+                let mut monitor = lock_device_monitor();
+                // Keep the order of registration, the new listeners last
+                listeners.append(&mut monitor.listeners);
+                monitor.listeners = listeners;
+                if monitor.listeners.is_empty() {
+                    monitor.running = false;
+                    break;
+                }
+            }
+        })
+        .unwrap();
+}
+
 extern "C" fn cleanup_openal_context() {
     if let Ok(mut guard) = AL_CONTEXT.lock() {
-        if let Ok(ref mut context) = *guard {
+        if let Some(Ok(context)) = guard.take() {
             unsafe {
                 ffi::alcDestroyContext(context.al_context);
                 if context.al_capt_device != 0 {
@@ -233,3 +713,15 @@ macro_rules! check_openal_context(
             }
         );
 );
+
+#[cfg(test)]
+mod test {
+    use internal::merge_attributes;
+
+    #[test]
+    fn merge_attributes_replaces_and_appends() -> () {
+        let list = [0x1007, 44100, 0x1992, 0, 0];
+        let merged = merge_attributes(&list, &[0x1992, 1, 0x1996, 2]);
+        assert_eq!(merged, vec![0x1007, 44100, 0x1992, 1, 0x1996, 2, 0]);
+    }
+}
@@ -0,0 +1,419 @@
+//! Several Musics streamed as the layers of a single piece.
+
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+use audio_controller::{AudioController, AudioControllerExt};
+use error::SoundError;
+use fade::FadeCurve;
+use internal::OpenAlData;
+use music;
+use music::Music;
+use openal::al;
+use states::State;
+use states::State::{Initial, Paused, Playing};
+use streaming;
+
+/**
+ * The stems of a piece, played on the same sample.
+ *
+ * Each layer is streamed like a Music, but the layers are refilled
+ * together and share one position: they start, pause, resume and seek as
+ * one, so they stay sample locked while the volume of each is changed on
+ * its own.
+ *
+ * The layers must have the same sample rate and length, they may have a
+ * different number of channels.
+ *
+ * # Example
+ * ```no_run
+ * use ears::{FadeCurve, LayeredMusic, SoundError};
+ * use std::time::Duration;
+ *
+ * fn main() -> Result<(), SoundError> {
+ *     let mut theme = LayeredMusic::new(&["path/to/pads.ogg", "path/to/drums.ogg"])?;
+ *     theme.set_layer_volume(1, 0.);
+ *     theme.set_looping(true);
+ *     theme.play();
+ *
+ *     // The action starts, bring the drums in
+ *     theme.fade_layer_to(1, 1., Duration::from_secs(2), FadeCurve::EqualPower);
+ *     Ok(())
+ * }
+ * ```
+ */
+pub struct LayeredMusic {
+    /// The layers, the first one giving the state and position of all
+    layers: Vec<Music>,
+    /// Channel to tell the thread to move every layer to a frame
+    offset_sender: Option<Sender<i64>>,
+}
+
+impl LayeredMusic {
+    /**
+     * Create a LayeredMusic with a layer for each file.
+     *
+     * # Argument
+     * * `paths` - The paths of the files of the layers
+     *
+     * # Return
+     * A `Result` containing Ok(LayeredMusic) on success, Err(SoundError)
+     * if a file can't be loaded, or if there is no file or the files don't
+     * have the same sample rate and length.
+     */
+    pub fn new(paths: &[&str]) -> Result<LayeredMusic, SoundError> {
+        let mut layers = Vec::with_capacity(paths.len());
+        for path in paths {
+            layers.push(Music::new(path)?);
+        }
+        LayeredMusic::from_musics(layers)
+    }
+
+    /**
+     * Create a LayeredMusic from Musics, each one being a layer.
+     *
+     * The settings of the Musics, like their volume or position in space,
+     * are kept. The buffers, offset and loop points of the first one are
+     * used by every layer, so the layers read the same frames. Only their
+     * first track is played.
+     *
+     * # Argument
+     * * `layers` - The Musics to play together
+     *
+     * # Return
+     * A `Result` containing Ok(LayeredMusic) on success,
+     * Err(SoundError::InvalidFormat) if there is no Music or the Musics
+     * don't have the same sample rate and length.
+     */
+    pub fn from_musics(layers: Vec<Music>) -> Result<LayeredMusic, SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let (samplerate, frames) = match layers.first() {
+            Some(first) => {
+                let infos = music::get_sndinfo(first);
+                (infos.samplerate, infos.frames)
+            }
+            None => return Err(SoundError::InvalidFormat),
+        };
+        let aligned = layers.iter().all(|layer| {
+            let infos = music::get_sndinfo(layer);
+            infos.samplerate == samplerate && infos.frames == frames
+        });
+        if !aligned {
+            return Err(SoundError::InvalidFormat);
+        }
+
+        Ok(LayeredMusic {
+            layers: layers,
+            offset_sender: None,
+        })
+    }
+
+    /**
+     * Get the number of layers.
+     */
+    pub fn get_layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /**
+     * Set the volume of a layer.
+     *
+     * Stops the fade of the layer, if any.
+     *
+     * # Arguments
+     * * `layer` - The index of the layer, in the order it was given
+     * * `volume` - The volume of the layer, 1.0 being unattenuated
+     *
+     * # Panics
+     * If there is no such layer.
+     */
+    pub fn set_layer_volume(&mut self, layer: usize, volume: f32) -> () {
+        self.layers[layer].set_volume(volume);
+    }
+
+    /**
+     * Get the volume of a layer.
+     *
+     * # Argument
+     * * `layer` - The index of the layer, in the order it was given
+     *
+     * # Panics
+     * If there is no such layer.
+     */
+    pub fn get_layer_volume(&self, layer: usize) -> f32 {
+        self.layers[layer].get_volume()
+    }
+
+    /**
+     * Fade the volume of a layer to a value.
+     *
+     * # Arguments
+     * * `layer` - The index of the layer, in the order it was given
+     * * `volume` - The volume to reach
+     * * `duration` - The duration of the fade
+     * * `curve` - The shape of the fade
+     *
+     * # Panics
+     * If there is no such layer.
+     */
+    pub fn fade_layer_to(
+        &mut self,
+        layer: usize,
+        volume: f32,
+        duration: Duration,
+        curve: FadeCurve,
+    ) -> () {
+        self.layers[layer].fade_to(volume, duration, curve);
+    }
+
+    /**
+     * Play or resume every layer, on the same sample.
+     */
+    pub fn play(&mut self) -> () {
+        check_openal_context!(());
+
+        let (offset_sender, offset_receiver) = channel();
+        let (sources, stream) = music::prepare_layers(&mut self.layers, offset_receiver);
+        if stream.is_some() {
+            self.offset_sender = Some(offset_sender);
+        }
+
+        al::alSourcePlayv(&sources);
+        for source in &sources {
+            streaming::started(*source);
+        }
+        if let Some(stream) = stream {
+            streaming::add(stream);
+        }
+    }
+
+    /**
+     * Pause every layer.
+     */
+    pub fn pause(&mut self) -> () {
+        check_openal_context!(());
+
+        let sources: Vec<u32> = self.layers.iter().map(music::get_source).collect();
+        al::alSourcePausev(&sources);
+    }
+
+    /**
+     * Stop every layer.
+     */
+    pub fn stop(&mut self) -> () {
+        for layer in &mut self.layers {
+            layer.stop();
+        }
+    }
+
+    /**
+     * Check if the layers are playing.
+     */
+    pub fn is_playing(&self) -> bool {
+        self.get_state() == Playing
+    }
+
+    /**
+     * Get the state of the layers.
+     *
+     * # Return
+     * The state of the first layer, shared by all.
+     */
+    pub fn get_state(&self) -> State {
+        check_openal_context!(Initial);
+
+        self.layers[0].get_state()
+    }
+
+    /**
+     * Set every layer looping or not.
+     *
+     * # Argument
+     * * `looping` - The new looping state
+     */
+    pub fn set_looping(&mut self, looping: bool) -> () {
+        for layer in &mut self.layers {
+            layer.set_looping(looping);
+        }
+    }
+
+    /**
+     * Check if the layers are looping.
+     */
+    pub fn is_looping(&self) -> bool {
+        self.layers[0].is_looping()
+    }
+
+    /**
+     * Move every layer to a frame.
+     *
+     * Playing layers are refilled and started again together, a paused
+     * LayeredMusic moves once it is resumed.
+     *
+     * # Argument
+     * * `offset` - The frame to seek to
+     */
+    pub fn set_offset(&mut self, offset: i32) -> () {
+        if !self.send_offset(offset.into()) {
+            for layer in &mut self.layers {
+                layer.set_offset(offset);
+            }
+        }
+    }
+
+    /**
+     * Get the frame being played, shared by every layer.
+     */
+    pub fn get_offset(&self) -> i32 {
+        self.layers[0].get_offset()
+    }
+
+    /**
+     * Move every layer to a time.
+     *
+     * Like set_offset, playing layers are started again together.
+     *
+     * # Argument
+     * * `position` - The time from the start of the layers
+     */
+    pub fn seek(&mut self, position: Duration) -> () {
+        let samplerate = music::get_sndinfo(&self.layers[0]).samplerate as f64;
+        let frame = (position.as_secs_f64() * samplerate).round() as i64;
+        if !self.send_offset(frame) {
+            for layer in &mut self.layers {
+                layer.seek(position);
+            }
+        }
+    }
+
+    /**
+     * Get the time being heard, shared by every layer.
+     */
+    pub fn position(&self) -> Duration {
+        self.layers[0].position()
+    }
+
+    /**
+     * Get the duration of the layers.
+     */
+    pub fn get_duration(&self) -> Duration {
+        self.layers[0].get_duration()
+    }
+
+    // Tell the streaming thread to move the layers to a frame, false if
+    // they aren't streamed.
+    fn send_offset(&mut self, offset: i64) -> bool {
+        let is_streaming = matches!(self.get_state(), Playing | Paused);
+
+        match self.offset_sender {
+            Some(ref sender) if is_streaming => {
+                sender.send(offset);
+                streaming::wake();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use audio_controller::AudioController;
+    use error::SoundError;
+    use headless;
+    use layered_music::LayeredMusic;
+    use music::Music;
+    use states::State::{Paused, Playing};
+    use std::time::Duration;
+
+    fn layer(samples: &[i16]) -> Music {
+        Music::from_bytes(headless::wav(samples)).expect("Cannot create Music")
+    }
+
+    #[test]
+    fn layered_music_create_FAIL() -> () {
+        let _harness = headless::lock();
+
+        match LayeredMusic::from_musics(Vec::new()) {
+            Err(SoundError::InvalidFormat) => {}
+            _ => panic!("A LayeredMusic needs a layer"),
+        }
+        match LayeredMusic::from_musics(vec![layer(&[0; 4410]), layer(&[0; 8820])]) {
+            Err(SoundError::InvalidFormat) => {}
+            _ => panic!("Layers of different lengths were accepted"),
+        }
+    }
+
+    #[test]
+    fn layered_music_layer_volume_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut layers =
+            LayeredMusic::from_musics(vec![layer(&[0; 44100]), layer(&[10000; 44100])])
+                .expect("Cannot create LayeredMusic");
+        assert_eq!(layers.get_layer_count(), 2);
+
+        layers.play();
+        assert_eq!(layers.get_state(), Playing);
+        assert!(!headless::is_silent(&harness.render_short()));
+
+        layers.set_layer_volume(1, 0.);
+        assert_eq!(layers.get_layer_volume(1), 0.);
+        assert!(headless::is_silent(&harness.render_short()));
+
+        layers.pause();
+        assert_eq!(layers.get_state(), Paused);
+        layers.stop();
+    }
+
+    #[test]
+    fn layered_music_offset_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut layers =
+            LayeredMusic::from_musics(vec![layer(&[1000; 88200]), layer(&[-1000; 88200])])
+                .expect("Cannot create LayeredMusic");
+        layers.play();
+        harness.render(Duration::from_millis(100));
+
+        layers.set_offset(44100);
+        harness.render(Duration::from_millis(100));
+
+        // Every layer moved to the same frame
+        let offset = layers.get_offset();
+        assert!(offset >= 44100);
+        for music in &layers.layers {
+            assert_eq!(music.get_offset(), offset);
+        }
+        layers.stop();
+    }
+
+    #[test]
+    fn layered_music_underrun_OK() -> () {
+        let mut harness = headless::lock();
+
+        // The buffers of the first layer are used by every layer
+        let mut first = layer(&[1000; 44100]);
+        first.set_buffer_duration(Duration::from_millis(1));
+        let mut layers = LayeredMusic::from_musics(vec![first, layer(&[-1000; 44100])])
+            .expect("Cannot create LayeredMusic");
+        layers.set_looping(true);
+        layers.play();
+
+        // The buffers are played faster than the refills between two
+        // rendered chunks, the layers restart together
+        harness.render_short();
+
+        assert!(layers.layers[0].get_underrun_count() > 0);
+        assert!(layers.layers.iter().all(|music| music.is_playing()));
+        let offset = layers.get_offset();
+        for music in &layers.layers {
+            assert_eq!(music.get_buffer_duration(), Duration::from_millis(1));
+            assert_eq!(music.get_offset(), offset);
+        }
+        layers.stop();
+    }
+}
@@ -35,7 +35,7 @@ A simple library to play sounds and music in Rust, using OpenAL and libsndfile.
 
 ```no_run
 extern crate ears;
-use ears::{Sound, SoundError, AudioController};
+use ears::{Sound, SoundError, AudioController, AudioControllerExt};
 
 fn main() -> Result<(), SoundError> {
     // Create a new Sound.
@@ -45,7 +45,7 @@ fn main() -> Result<(), SoundError> {
     snd.play();
 
     // Wait until the end of the sound
-    while snd.is_playing() {}
+    snd.finished().recv().ok();
 
     Ok(())
 }
@@ -70,16 +70,25 @@ extern crate libc;
 extern crate lazy_static;
 
 // Reexport public API
-pub use audio_controller::AudioController;
+pub use audio_context::{AudioContext, AudioContextBuilder};
+pub use audio_controller::{AudioController, AudioControllerExt};
 pub use audio_tags::{AudioTags, Tags};
-pub use einit::{init, init_in};
+pub use beat_clock::{BeatClock, BeatPosition, Quantize};
+pub use compression::{get_compression, set_compression, Compression};
+pub use einit::{init, init_in, init_with_device};
 pub use error::SoundError;
+pub use fade::FadeCurve;
 pub use internal::OpenAlContextError;
+pub use layered_music::LayeredMusic;
+pub use loopback::Loopback;
+pub use markers::Marker;
 pub use music::Music;
+pub use play_group::PlayGroup;
 pub use presets::ReverbPreset;
 pub use record_context::RecordContext;
 pub use recorder::Recorder;
 pub use reverb_effect::ReverbEffect;
+pub use sample_format::{get_sample_format, set_sample_format, SampleFormat};
 pub use sound::Sound;
 pub use sound_data::SoundData;
 pub use states::State;
@@ -89,19 +98,36 @@ mod internal;
 mod openal;
 mod sndfile;
 
+// Headless context for the tests
+#[cfg(test)]
+mod headless;
+
 // The public ears API
 
+mod audio_context;
 mod audio_controller;
 mod audio_tags;
+mod beat_clock;
+mod compression;
+pub mod device;
 #[path = "init.rs"]
 mod einit;
 mod error;
+mod fade;
+pub mod hrtf;
+mod layered_music;
 pub mod listener;
+mod loopback;
+mod markers;
 mod music;
+mod play_group;
 mod presets;
 mod record_context;
 mod recorder;
 mod reverb_effect;
+mod sample_format;
 mod sound;
 mod sound_data;
 mod states;
+mod streaming;
+mod vorbis_comments;
@@ -197,30 +197,48 @@ pub fn get_velocity() -> [f32; 3] {
 
 #[cfg(test)]
 mod test {
+    use audio_controller::AudioController;
+    use headless;
     use listener::{
         get_orientation, get_position, get_volume, set_orientation, set_position, set_volume,
     };
+    use sound::Sound;
 
     #[test]
-    #[ignore]
     pub fn listener_set_volume() -> () {
+        let _harness = headless::lock();
+
         set_volume(0.77);
         assert_eq!(get_volume(), 0.77);
     }
 
+    #[test]
+    pub fn listener_set_volume_zero_silent() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+
+        set_volume(0.);
+        snd.play();
+        assert!(headless::is_silent(&harness.render_short()));
+        snd.stop();
+    }
+
     // untill https://github.com/rust-lang/rust/issues/7622 is not used, slice comparsion is used
 
     #[test]
-    #[ignore]
     pub fn listener_set_position() -> () {
+        let _harness = headless::lock();
+
         set_position([50f32, 150f32, 234f32]);
         let res = get_position();
         assert_eq!((res[0], res[1], res[2]), (50f32, 150f32, 234f32))
     }
 
     #[test]
-    #[ignore]
     pub fn listener_set_orientation() -> () {
+        let _harness = headless::lock();
+
         set_orientation([50., 150., 234.], [277., 125., 71.]);
         let (s1, s2) = get_orientation();
         assert_eq!(s1, [50f32, 150f32, 234f32]);
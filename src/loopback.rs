@@ -0,0 +1,224 @@
+//! Offline rendering through a loopback device.
+
+use libc::c_void;
+use std::mem;
+use std::time::Duration;
+
+use internal::{OpenAlContextError, OpenAlData};
+use openal::{al, ffi};
+use sndfile::FormatType::{FormatPcm16, FormatWav};
+use sndfile::OpenMode::Write;
+use sndfile::{SndFile, SndInfo};
+use streaming;
+
+/// Number of frames rendered at once when writing to a file.
+const FILE_CHUNK_FRAMES: usize = 4096;
+
+/// Duration rendered between two refills of the Musics, in milliseconds.
+const STREAM_CHUNK_MS: usize = 10;
+
+/**
+ * Render the audio scene on demand instead of playing it.
+ *
+ * A Loopback replaces the output device by the ALC_SOFT_loopback device of
+ * OpenAL Soft: nothing is played on the sound card, the mix of every Sound,
+ * Music and ReverbEffect is produced as interleaved 16 bit samples each
+ * time the application asks for it, as fast as the machine allows.
+ *
+ * It must be created before any other use of __ears__.
+ *
+ * Musics are refilled between rendered chunks of 10 milliseconds, they
+ * keep playing without gaps however fast the scene is rendered as long as
+ * their buffers last longer than that.
+ *
+ * # Example
+ * ```no_run
+ * use ears::{AudioController, Loopback, Sound};
+ * use std::time::Duration;
+ *
+ * fn main() {
+ *     let mut loopback = Loopback::new(44100, 2).expect("Cannot create the loopback device");
+ *
+ *     let mut snd = Sound::new("path/to/my/sound.ogg").unwrap();
+ *     snd.play();
+ *
+ *     // Render the first two seconds of the scene in a file
+ *     loopback.render_to_file("scene.wav", Duration::from_secs(2));
+ * }
+ * ```
+ */
+pub struct Loopback {
+    /// The loopback device
+    al_device: ffi::ALCdevicePtr,
+    /// The alcRenderSamplesSOFT function of the extension
+    render_samples: ffi::LPALCRENDERSAMPLESSOFT,
+    /// The frequency of the rendered samples
+    sample_rate: i32,
+    /// The number of interleaved channels per frame
+    channels: i32,
+}
+
+impl Loopback {
+    /**
+     * Create the loopback device and the audio context rendering to it.
+     *
+     * # Arguments
+     * * `sample_rate` - The frequency of the rendered samples, in Hz
+     * * `channels` - The number of channels of the rendered frames, one of
+     * 1, 2, 4, 6, 7 or 8
+     *
+     * # Return
+     * `Ok(Loopback)` on success, `Err(OpenAlContextError)` if the extension
+     * is missing, the format is unsupported or __ears__ is already
+     * initialized.
+     */
+    pub fn new(sample_rate: i32, channels: i32) -> Result<Loopback, OpenAlContextError> {
+        let channel_config = match channels {
+            1 => ffi::ALC_MONO_SOFT,
+            2 => ffi::ALC_STEREO_SOFT,
+            4 => ffi::ALC_QUAD_SOFT,
+            6 => ffi::ALC_5POINT1_SOFT,
+            7 => ffi::ALC_6POINT1_SOFT,
+            8 => ffi::ALC_7POINT1_SOFT,
+            _ => return Err(OpenAlContextError::UnsupportedRenderFormat),
+        };
+
+        let device = OpenAlData::init_loopback(sample_rate, channel_config, ffi::ALC_SHORT_SOFT)?;
+        let render_samples: ffi::LPALCRENDERSAMPLESSOFT =
+            match al::alcGetProcAddress(device, "alcRenderSamplesSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALCRENDERSAMPLESSOFT>(ptr)
+                },
+                None => {
+                    return Err(OpenAlContextError::MissingExtension(
+                        "ALC_SOFT_loopback".to_string(),
+                    ))
+                }
+            };
+
+        Ok(Loopback {
+            al_device: device,
+            render_samples: render_samples,
+            sample_rate: sample_rate,
+            channels: channels,
+        })
+    }
+
+    /// Get the frequency of the rendered samples, in Hz.
+    pub fn get_sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    /// Get the number of interleaved channels per rendered frame.
+    pub fn get_channels(&self) -> i32 {
+        self.channels
+    }
+
+    /**
+     * Render the next frames of the scene into a slice.
+     *
+     * # Argument
+     * * `samples` - The slice to fill with interleaved samples, only whole
+     * frames are rendered
+     */
+    pub fn render_into(&mut self, samples: &mut [i16]) {
+        let channels = self.channels as usize;
+        let chunk_frames = (self.sample_rate as usize * STREAM_CHUNK_MS / 1000).max(1);
+        let frames = samples.len() / channels;
+
+        // Refill the Musics between the chunks, the streaming thread would
+        // be late when rendering faster than real time
+        for chunk in samples[..frames * channels].chunks_mut(chunk_frames * channels) {
+            streaming::pump();
+            unsafe {
+                (self.render_samples)(
+                    self.al_device,
+                    chunk.as_mut_ptr() as *mut c_void,
+                    (chunk.len() / channels) as i32,
+                );
+            }
+        }
+        streaming::pump();
+    }
+
+    /**
+     * Render the next frames of the scene.
+     *
+     * # Argument
+     * * `frames` - The number of frames to render
+     *
+     * # Return
+     * The interleaved samples, `frames * channels` of them.
+     */
+    pub fn render(&mut self, frames: usize) -> Vec<i16> {
+        let mut samples = vec![0i16; frames * self.channels as usize];
+        self.render_into(&mut samples);
+        samples
+    }
+
+    /**
+     * Render the next part of the scene.
+     *
+     * # Argument
+     * * `duration` - The duration to render
+     *
+     * # Return
+     * The interleaved samples.
+     */
+    pub fn render_duration(&mut self, duration: Duration) -> Vec<i16> {
+        let frames = self.duration_to_frames(duration);
+        self.render(frames)
+    }
+
+    /**
+     * Render the next part of the scene in a 16 bit WAV file.
+     *
+     * # Arguments
+     * * `path` - The path of the file to create
+     * * `duration` - The duration to render
+     *
+     * # Return
+     * true if the file was written, false otherwise.
+     */
+    pub fn render_to_file(&mut self, path: &str, duration: Duration) -> bool {
+        let infos = Box::new(SndInfo {
+            frames: 0,
+            samplerate: self.sample_rate,
+            channels: self.channels,
+            format: (FormatPcm16 | FormatWav) as i32,
+            sections: 0,
+            seekable: 0,
+        });
+
+        let mut file = match SndFile::new_with_info(path, Write, infos) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("{}", e);
+                return false;
+            }
+        };
+
+        let mut remaining = self.duration_to_frames(duration);
+        let mut samples = vec![0i16; FILE_CHUNK_FRAMES * self.channels as usize];
+        while remaining > 0 {
+            let frames = remaining.min(FILE_CHUNK_FRAMES);
+            let len = frames * self.channels as usize;
+            self.render_into(&mut samples[..len]);
+            if file.writef_i16(&mut samples[..len], frames as i64) != frames as i64 {
+                println!("{}", file.string_error());
+                file.close();
+                return false;
+            }
+            remaining -= frames;
+        }
+        file.close();
+        true
+    }
+
+    fn duration_to_frames(&self, duration: Duration) -> usize {
+        let sample_rate = self.sample_rate as u64;
+        let frames = duration.as_secs() * sample_rate
+            + duration.subsec_nanos() as u64 * sample_rate / 1_000_000_000;
+        frames as usize
+    }
+}
@@ -0,0 +1,208 @@
+//! Named positions of a Sound or a Music reported while they play.
+
+use std::sync::{Arc, Mutex};
+
+use streaming::Callback;
+
+/// Time between two checks of the position of a source reporting markers.
+pub const MARKER_INTERVAL_MS: u64 = 5;
+
+/**
+ * A named frame of a Sound or a Music.
+ *
+ * Markers are read from the cue points of WAV files or added by hand, the
+ * callback registered with `on_marker` is called each time the playback
+ * crosses one of them.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    /// The name of the marker, the identifier of the cue point if the file
+    /// doesn't name it
+    pub name: String,
+    /// The frame the marker is at
+    pub frame: i64,
+}
+
+impl Marker {
+    /// Create a marker.
+    pub fn new(name: &str, frame: i64) -> Marker {
+        Marker {
+            name: name.to_string(),
+            frame: frame,
+        }
+    }
+}
+
+/// The callback of a source called with the reached markers, shared with
+/// the streaming thread.
+pub type MarkerCallback = Arc<Mutex<Option<Box<dyn FnMut(&Marker) + Send>>>>;
+
+/// Create an empty callback slot.
+pub fn new_callback() -> MarkerCallback {
+    Arc::new(Mutex::new(None))
+}
+
+/// Check if a callback is set in the slot.
+pub fn has_callback(callback: &MarkerCallback) -> bool {
+    match callback.lock() {
+        Ok(guard) => guard.is_some(),
+        Err(poisoned) => poisoned.into_inner().is_some(),
+    }
+}
+
+/// Put a callback in the slot, replacing the previous one.
+pub fn set_callback(callback: &MarkerCallback, function: Box<dyn FnMut(&Marker) + Send>) -> () {
+    let mut guard = match callback.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *guard = Some(function);
+}
+
+/// Get the call of the callback for a reached marker, to be made once the
+/// streaming thread released its lock.
+pub fn notify(callback: &MarkerCallback, marker: Marker) -> Callback {
+    let callback = callback.clone();
+    Box::new(move || {
+        let mut guard = match callback.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(ref mut function) = *guard {
+            function(&marker);
+        }
+    })
+}
+
+/// Insert a marker, keeping the markers sorted by frame.
+pub fn insert(markers: &mut Vec<Marker>, marker: Marker) -> () {
+    let index = markers
+        .iter()
+        .position(|other| other.frame > marker.frame)
+        .unwrap_or(markers.len());
+    markers.insert(index, marker);
+}
+
+/// Follows the playback of a source to find the markers it crosses.
+pub struct MarkerTracker {
+    /// The last frame whose markers were reported
+    last: i64,
+}
+
+impl MarkerTracker {
+    /// Create a tracker for a playback starting at a frame.
+    pub fn new(start: i64) -> MarkerTracker {
+        MarkerTracker { last: start - 1 }
+    }
+
+    /// Move the playback to a frame, the markers skipped aren't reported.
+    pub fn jump(&mut self, frame: i64) -> () {
+        self.last = frame - 1;
+    }
+
+    /**
+     * Move the playback forward to a frame.
+     *
+     * # Arguments
+     * * `markers` - The markers of the source, sorted by frame
+     * * `position` - The frame being played
+     * * `loop_region` - The looped frames, the end being excluded, if the
+     * source loops
+     *
+     * # Return
+     * The markers crossed since the last call, in playback order. When the
+     * position went back to the start of the loop, the end of the loop is
+     * reported before its start.
+     */
+    pub fn advance(
+        &mut self,
+        markers: &[Marker],
+        position: i64,
+        loop_region: Option<(i64, i64)>,
+    ) -> Vec<Marker> {
+        let last = self.last;
+        let crossed = if position >= last {
+            markers
+                .iter()
+                .filter(|marker| marker.frame > last && marker.frame <= position)
+                .cloned()
+                .collect()
+        } else {
+            match loop_region {
+                Some((start, end)) => markers
+                    .iter()
+                    .filter(|marker| marker.frame > last && marker.frame < end)
+                    .chain(
+                        markers
+                            .iter()
+                            .filter(|marker| marker.frame >= start && marker.frame <= position),
+                    )
+                    .cloned()
+                    .collect(),
+                // Moved back without looping, nothing was crossed
+                None => Vec::new(),
+            }
+        };
+        self.last = position;
+        crossed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use markers::{insert, Marker, MarkerTracker};
+
+    fn names(markers: Vec<Marker>) -> Vec<String> {
+        markers.into_iter().map(|marker| marker.name).collect()
+    }
+
+    fn markers() -> Vec<Marker> {
+        let mut markers = Vec::new();
+        insert(&mut markers, Marker::new("c", 300));
+        insert(&mut markers, Marker::new("a", 0));
+        insert(&mut markers, Marker::new("b", 100));
+        markers
+    }
+
+    #[test]
+    fn markers_insert_sorted() -> () {
+        assert_eq!(names(markers()), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn markers_tracker_advance() -> () {
+        let markers = markers();
+        let mut tracker = MarkerTracker::new(0);
+
+        assert_eq!(names(tracker.advance(&markers, 50, None)), vec!["a"]);
+        assert!(tracker.advance(&markers, 50, None).is_empty());
+        assert_eq!(names(tracker.advance(&markers, 400, None)), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn markers_tracker_loop() -> () {
+        let markers = markers();
+        let mut tracker = MarkerTracker::new(0);
+        tracker.advance(&markers, 250, Some((50, 350)));
+
+        // Every pass reports the markers again, from the loop start
+        assert_eq!(
+            names(tracker.advance(&markers, 120, Some((50, 350)))),
+            vec!["c", "b"]
+        );
+        assert_eq!(
+            names(tracker.advance(&markers, 110, Some((50, 350)))),
+            vec!["c", "b"]
+        );
+    }
+
+    #[test]
+    fn markers_tracker_jump() -> () {
+        let markers = markers();
+        let mut tracker = MarkerTracker::new(0);
+        tracker.jump(200);
+
+        assert_eq!(names(tracker.advance(&markers, 300, None)), vec!["c"]);
+        assert!(tracker.advance(&markers, 10, None).is_empty());
+    }
+}
@@ -21,38 +21,51 @@
 
 //! Play Music easily.
 
-use libc::c_void;
+use std::collections::VecDeque;
 use std::convert::TryInto;
-use std::mem;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Arc;
-use std::thread;
-use std::thread::sleep;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 use std::vec::Vec;
 
-use audio_controller::AudioController;
+use audio_controller::{AudioController, AudioControllerExt};
 use audio_tags::{get_sound_tags, AudioTags, Tags};
+use beat_clock::{BeatClock, BeatPosition, Quantize};
 use error::SoundError;
+use fade::{FadeCurve, Ramp};
 use internal::OpenAlData;
+use markers;
+use markers::{Marker, MarkerCallback, MarkerTracker};
 use openal::{al, ffi};
 use reverb_effect::ReverbEffect;
-use sndfile::OpenMode::Read;
+use sample_format::{get_float_format, Samples};
 use sndfile::SeekMode::SeekSet;
-use sndfile::{SndFile, SndInfo};
+use sndfile::{SndFile, SndFileError, SndInfo};
 use states::State;
 use states::State::{Initial, Paused, Playing, Stopped};
+use streaming;
+use streaming::{Callback, Stream};
+use vorbis_comments;
 
-const BUFFER_COUNT: i32 = 2;
+/// Number of buffers queued by default.
+const DEFAULT_BUFFER_COUNT: i32 = 2;
+
+/// Duration of each buffer by default, in milliseconds.
+const DEFAULT_BUFFER_DURATION_MS: u64 = 1000;
+
+/// Longest wait of the streaming thread between two refills.
+const MAX_POLL_INTERVAL_MS: u64 = 50;
 
 /**
  * Play Music easily.
  *
  * Simple class to play music easily in 2 lines.
  *
- * Music is played in their own task and the samples are loaded progressively
- * using circular buffers.
+ * Musics are streamed by a background thread shared by all of them, the
+ * samples are loaded progressively using circular buffers.
  *
  * Music maintains it's own cursor into the underlying file and will use that
  * cursor to continuously refill each buffer as it's processed by the source.
@@ -75,19 +88,29 @@ pub struct Music {
     /// The internal OpenAL source identifier
     al_source: u32,
     /// The internal OpenAL buffers
-    al_buffers: [u32; 2],
-    /// The file open with libmscfile
-    file: Option<Box<SndFile>>,
-    /// Information of the file
+    al_buffers: Vec<u32>,
+    /// The files played one after the other, shared with the streaming thread
+    playlist: Arc<Mutex<Playlist>>,
+    /// Information of the first file, shared by all the tracks
     file_infos: SndInfo,
     /// Quantity of sample to read each time
     sample_to_read: i64, // TODO: usize?
+    /// Number of buffers queued from the next play
+    buffer_count: i32,
+    /// Duration of each buffer from the next play
+    buffer_duration: Duration,
+    /// Number of times the source ran out of samples while streaming
+    underruns: Arc<AtomicU32>,
     /// Format of the sample
     sample_format: i32,
+    /// Whether the samples are streamed as float
+    float_samples: bool,
     /// Audio tags
     sound_tags: Tags,
     /// Current cursor into the music file
     cursor: Arc<AtomicI64>,
+    /// Loop points, shared with the streaming thread
+    loop_points: Arc<LoopPoints>,
     /// State
     state: State,
     /// Whether this music is looping or not
@@ -95,11 +118,123 @@ pub struct Music {
     /// Channel to tell the thread, if is_looping changed
     looping_sender: Option<Sender<bool>>,
 
-    /// Channel to tell the thread to set offset
-    offset_sender: Option<Sender<i32>>,
+    /// Channel to tell the thread to set the track and its offset
+    offset_sender: Option<Sender<(usize, i64)>>,
+    /// Function called with the markers reached, shared with the streaming
+    /// thread
+    marker_callback: MarkerCallback,
+    /// Musical clock of the Music, if any
+    beat_clock: Option<BeatClock>,
+    /// Changes waiting for a beat or a bar, shared with the streaming thread
+    beat_jobs: Arc<Mutex<Vec<BeatJob>>>,
+}
+
+/// What to do once the playback reaches a beat or a bar.
+enum BeatAction {
+    Call(Box<dyn FnOnce(BeatPosition) + Send>),
+    Skip,
+}
+
+/// A change scheduled on the beat clock of a Music.
+struct BeatJob {
+    /// Track the change happens in
+    track: usize,
+    /// Frame the change happens at
+    frame: i64,
+    /// Frame being played when the change was scheduled
+    scheduled: i64,
+    /// Musical position of the frame
+    position: BeatPosition,
+    action: BeatAction,
+}
+
+/// The part of the file played again and again by a looping Music.
+struct LoopPoints {
+    /// First frame and excluded end frame of the loop
+    frames: Mutex<(i64, i64)>,
+    /// Whether the stream went back to the loop start since the playback
+    /// started or the offset changed
+    wrapped: AtomicBool,
+}
+
+impl LoopPoints {
+    fn get(&self) -> (i64, i64) {
+        match self.frames.lock() {
+            Ok(guard) => *guard,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+
+    fn set(&self, start: i64, end: i64) -> () {
+        match self.frames.lock() {
+            Ok(mut guard) => *guard = (start, end),
+            Err(poisoned) => *poisoned.into_inner() = (start, end),
+        }
+    }
+}
+
+/// A file of the playlist of a Music.
+struct Track {
+    file: SndFile,
+    tags: Tags,
+    /// First frame and excluded end frame of the loop of the track
+    loop_points: (i64, i64),
+    /// Markers of the track, sorted by frame
+    markers: Vec<Marker>,
+}
+
+impl Track {
+    /// Open the file of a track.
+    fn open(path: &str) -> Result<Track, SoundError> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => return Err(SoundError::LoadError(SndFileError::new(err.to_string()))),
+        };
+        // The loop points may be stored in the comments of an Ogg file
+        let comment_loop = vorbis_comments::read_loop_region(&mut file);
+
+        match SndFile::new_from_reader(file) {
+            Ok(file) => Ok(Track::new(file, comment_loop)),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /// Create a track looping between the loop points of the file or the
+    /// ones found in its comments, over the whole file otherwise, with the
+    /// cue points of the file as markers.
+    fn new(file: SndFile, comment_loop: Option<(i64, i64)>) -> Track {
+        let frames = file.get_sndinfo().frames;
+        let loop_points = match file.get_loop_points().or(comment_loop) {
+            Some((start, end)) if start < frames => (start, end.min(frames)),
+            _ => (0, frames),
+        };
+
+        let mut track_markers = Vec::new();
+        for (name, frame) in file.get_cue_points() {
+            markers::insert(&mut track_markers, Marker::new(&name, frame));
+        }
+
+        Track {
+            tags: get_sound_tags(&file),
+            file: file,
+            loop_points: loop_points,
+            markers: track_markers,
+        }
+    }
+
+    fn frames(&self) -> i64 {
+        self.file.get_sndinfo().frames
+    }
+}
 
-    /// Thread which streams the music file
-    thread_handle: Option<thread::JoinHandle<()>>,
+/// The tracks of a Music, shared with its stream.
+struct Playlist {
+    tracks: Vec<Track>,
+    /// Index of the track being heard
+    current: usize,
+    /// Index of the track being read, ahead of the current one when the
+    /// beginning of the next track is already queued
+    decoding: usize,
 }
 
 // Recursively fill a buffer with data, returning the frame offset into
@@ -118,69 +253,107 @@ pub struct Music {
 // in each case.
 //
 // ref: http://www.mega-nerd.com/libsndfile/api.html#read
+//
+// When looping, the reads stop at the end of the loop and the cursor wraps
+// around to its start, so the part before the loop is only played once.
 fn fill_buffer(
-    samples: &mut Vec<i16>,
+    samples: &mut Samples,
     sndfile: &mut SndFile,
     cursor: Arc<AtomicI64>,
     is_looping: bool,
+    loop_points: &LoopPoints,
+    cut: Option<i64>,
 ) {
-    // First, find where the buffer is currently filled to
-    let buffer_position = samples.len();
-    let cursor_position = cursor.load(Ordering::Relaxed);
+    let (loop_start, loop_end) = loop_points.get();
+    let channels = sndfile.get_sndinfo().channels as i64;
 
-    // Move the sound file to where we want to read from
-    sndfile.seek(cursor_position, SeekSet);
+    // Read until the buffer is full, a short loop being read many times
+    loop {
+        // Nothing is read past the frame a skip moves to the next track at
+        let mut cursor_position = cursor.load(Ordering::Relaxed);
+        match cut {
+            Some(cut) if cursor_position >= cut => return,
+            _ => {}
+        }
 
-    // Read data from sound file into the buffer, from the current buffer position onwards
-    let read_amount = (samples.capacity() - samples.len()) as i64;
-    let read_length = sndfile.read_i16(&mut samples[buffer_position..], read_amount) as usize;
+        // The loop points may have moved before the cursor
+        if is_looping && cursor_position >= loop_end {
+            cursor_position = loop_start;
+            loop_points.wrapped.store(true, Ordering::Relaxed);
+        }
 
-    // Update the vector length manually
-    unsafe {
-        samples.set_len(buffer_position + read_length);
-    }
+        // Move the sound file to where we want to read from
+        sndfile.seek(cursor_position, SeekSet);
 
-    let channels = sndfile.get_sndinfo().channels as i64;
-    let frames = sndfile.get_sndinfo().frames;
+        // Read data from sound file into the buffer, from the current buffer position onwards,
+        // without going past the end of the loop or the cut
+        let end = match (is_looping, cut) {
+            (true, Some(cut)) => Some(loop_end.min(cut)),
+            (true, None) => Some(loop_end),
+            (false, cut) => cut,
+        };
+        let read_length = match end {
+            Some(end) => {
+                samples.read_at_most(sndfile, ((end - cursor_position) * channels) as usize)
+            }
+            None => samples.read(sndfile),
+        };
 
-    // Calculate where the next cursor is at, based on how many 'items' were read
-    // divided by the channels in the source sound file.
-    let mut new_cursor_position = cursor_position + read_length as i64 / channels;
+        // Calculate where the next cursor is at, based on how many 'items' were read
+        // divided by the channels in the source sound file.
+        let mut new_cursor_position = cursor_position + read_length as i64 / channels;
 
-    // Modulo on new cursor position to wrap around if we're looping
-    if is_looping {
-        new_cursor_position = new_cursor_position % frames;
-    }
+        // The cut ends the track, even in the middle of the loop
+        match cut {
+            Some(cut) if new_cursor_position >= cut => {
+                cursor.store(new_cursor_position, Ordering::Relaxed);
+                return;
+            }
+            _ => {}
+        }
+
+        // Wrap around to the start of the loop if we're looping
+        if is_looping && new_cursor_position >= loop_end {
+            new_cursor_position = loop_start;
+            loop_points.wrapped.store(true, Ordering::Relaxed);
+        }
 
-    cursor.store(new_cursor_position, Ordering::Relaxed);
+        cursor.store(new_cursor_position, Ordering::Relaxed);
 
-    // If we haven't reached capacity yet, keep recursing
-    if samples.len() != samples.capacity() && read_length > 0 {
-        fill_buffer(samples, sndfile, cursor, is_looping)
+        // Stop once the buffer is full or the file is read
+        if samples.len() == samples.capacity() || read_length == 0 {
+            return;
+        }
     }
 }
 
 // Becaused the Music source is playing buffered audio, we need to be
 // able to calculate the offset into the full file ourselves
 fn calculate_true_offset(
-    info: &SndInfo,
+    frames: i64,
     cursor: i64,
-    buffer_size: i64,
+    buffer_frames: i64,
     buffers_queued: i32,
     source_offset: i32,
-) -> i32 {
-    let queued_buffers_size = buffer_size / BUFFER_COUNT as i64 * buffers_queued as i64;
+    loop_points: &LoopPoints,
+) -> i64 {
+    let queued_buffers_size = buffer_frames * buffers_queued as i64;
     let offset = cursor - queued_buffers_size + source_offset as i64;
 
-    // This is a bit of a pro hack to deal with when the buffers wrap around
-    // when looping... seems to be accurate though
-    let offset = if offset < 0 {
-        info.frames + offset
+    // The queued buffers hold the end of the loop before its start when
+    // the stream wrapped around
+    let (loop_start, loop_end) = loop_points.get();
+    let offset = if loop_points.wrapped.load(Ordering::Relaxed) && offset < loop_start {
+        offset + loop_end - loop_start
     } else {
         offset
     };
 
-    offset.try_into().unwrap_or(0)
+    // This is a bit of a pro hack to deal with when the buffers wrap around
+    // when looping... seems to be accurate though
+    let offset = if offset < 0 { frames + offset } else { offset };
+
+    offset.max(0)
 }
 
 // Sets the new cursor from offset in seconds with reasonable accuracy
@@ -211,24 +384,77 @@ impl Music {
         check_openal_context!(Err(SoundError::InvalidOpenALContext));
 
         // Retrieve File and Music datas
-        let file = match SndFile::new(path, Read) {
-            Ok(file) => Box::new(file),
-            Err(err) => {
-                return Err(SoundError::LoadError(err));
-            }
-        };
-        let infos = file.get_sndinfo();
+        Music::from_track(Track::open(path)?)
+    }
+
+    /**
+     * Create a new Music from the content of a music file in memory.
+     *
+     * The samples are still decoded progressively while playing, only the
+     * encoded bytes are kept in memory.
+     *
+     * # Argument
+     * * `bytes` - The content of the music file
+     *
+     * # Return
+     * A `Result` containing Ok(Music) on success, Err(SoundError)
+     * if there has been an error.
+     */
+    pub fn from_bytes<B: AsRef<[u8]> + Send + 'static>(bytes: B) -> Result<Music, SoundError> {
+        // Check that OpenAL is launched
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let comment_loop = vorbis_comments::read_loop_region(&mut io::Cursor::new(bytes.as_ref()));
+
+        match SndFile::new_from_bytes(bytes) {
+            Ok(file) => Music::from_track(Track::new(file, comment_loop)),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /**
+     * Create a new Music from a music file read from a stream.
+     *
+     * The stream is read progressively while playing, from the streaming
+     * thread, so a large file in an archive or a custom virtual file
+     * system never has to be loaded at once.
+     *
+     * # Argument
+     * * `reader` - The stream containing the music file
+     *
+     * # Return
+     * A `Result` containing Ok(Music) on success, Err(SoundError)
+     * if there has been an error.
+     */
+    pub fn from_reader<R: io::Read + io::Seek + Send + 'static>(
+        mut reader: R,
+    ) -> Result<Music, SoundError> {
+        // Check that OpenAL is launched
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let comment_loop = vorbis_comments::read_loop_region(&mut reader);
+
+        match SndFile::new_from_reader(reader) {
+            Ok(file) => Music::from_track(Track::new(file, comment_loop)),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /// Create the source and the buffers streaming the first track.
+    fn from_track(track: Track) -> Result<Music, SoundError> {
+        let infos = track.file.get_sndinfo();
 
         // create the source and the buffers
         let mut source_id = 0;
-        let mut buffer_ids = [0; BUFFER_COUNT as usize];
+        let mut buffer_ids = vec![0; DEFAULT_BUFFER_COUNT as usize];
         // create the source
         al::alGenSources(1, &mut source_id);
         // create the buffers
-        al::alGenBuffers(BUFFER_COUNT, &mut buffer_ids[0]);
+        al::alGenBuffers(DEFAULT_BUFFER_COUNT, &mut buffer_ids[0]);
 
         // Retrieve format information
-        let format = match al::get_channels_format(infos.channels) {
+        let float_format = get_float_format(&infos);
+        let format = match float_format.or_else(|| al::get_channels_format(infos.channels)) {
             Some(fmt) => fmt,
             None => {
                 return Err(SoundError::InvalidFormat);
@@ -240,161 +466,1348 @@ impl Music {
             return Err(SoundError::InternalOpenALError(err));
         };
 
-        let sound_tags = get_sound_tags(&*file);
+        let sound_tags = track.tags.clone();
+        let (loop_start, loop_end) = track.loop_points;
 
         Ok(Music {
             al_source: source_id,
             al_buffers: buffer_ids,
-            file: Some(file),
-            sample_to_read: 50000 * (infos.channels as i64),
+            playlist: Arc::new(Mutex::new(Playlist {
+                tracks: vec![track],
+                current: 0,
+                decoding: 0,
+            })),
+            sample_to_read: 0,
+            buffer_count: DEFAULT_BUFFER_COUNT,
+            buffer_duration: Duration::from_millis(DEFAULT_BUFFER_DURATION_MS),
+            underruns: Arc::new(AtomicU32::new(0)),
             file_infos: infos,
             sample_format: format,
+            float_samples: float_format.is_some(),
             sound_tags: sound_tags,
             cursor: Arc::new(AtomicI64::new(0)),
+            loop_points: Arc::new(LoopPoints {
+                frames: Mutex::new((loop_start, loop_end)),
+                wrapped: AtomicBool::new(false),
+            }),
             state: Initial,
             is_looping: false,
             looping_sender: None,
             offset_sender: None,
-            thread_handle: None,
+            marker_callback: markers::new_callback(),
+            beat_clock: None,
+            beat_jobs: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
-    fn process_music(&mut self) -> () {
-        let (chan, port) = channel();
-        let sample_t_r = self.sample_to_read;
-        let sample_rate = self.file_infos.samplerate;
-        let sample_format = self.sample_format;
-        let al_source = self.al_source;
-        let al_buffers = self.al_buffers;
+    /**
+     * Set the loop points of the Music.
+     *
+     * A looping Music plays once from the beginning until the end point,
+     * then loops between the start and end points instead of the whole
+     * file. Loop points are read from the instrument chunk of the file or
+     * from the LOOPSTART and LOOPLENGTH comments of an Ogg file when it
+     * has them.
+     *
+     * They can be changed while the Music is playing, the new points are
+     * used once the frames already streamed are played. Each track of the
+     * playlist has its own loop points, these are the ones of the current
+     * track.
+     *
+     * # Arguments
+     * * `start` - The first frame of the loop
+     * * `end` - The frame ending the loop, excluded
+     *
+     * # Return
+     * `Ok(())` on success, Err(SoundError) if the points are out of the
+     * file.
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, Music, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   let mut msc = Music::new("path/to/my/theme.ogg")?;
+     *   // Play the intro once, then loop between the frames 441000 and 2646000
+     *   msc.set_loop_points(441000, 2646000)?;
+     *   msc.set_looping(true);
+     *   msc.play();
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn set_loop_points(&mut self, start: i64, end: i64) -> Result<(), SoundError> {
+        let mut playlist = self.lock_playlist();
+        let current = playlist.current;
+        if start < 0 || start >= end || end > playlist.tracks[current].frames() {
+            return Err(SoundError::InvalidLoopPoints);
+        }
 
-        // create sample buffer and reserve the exact capacity we need
-        let mut samples: Vec<i16> = Vec::with_capacity(sample_t_r as usize);
+        playlist.tracks[current].loop_points = (start, end);
+        if playlist.decoding == current {
+            self.loop_points.set(start, end);
+        }
+        Ok(())
+    }
 
-        fill_buffer(
-            &mut samples,
-            &mut self.file.as_mut().unwrap(),
-            self.cursor.clone(),
-            self.is_looping,
-        );
+    /**
+     * Remove the loop points of the Music, looping over the whole file.
+     */
+    pub fn clear_loop_points(&mut self) -> () {
+        let mut playlist = self.lock_playlist();
+        let current = playlist.current;
+        let frames = playlist.tracks[current].frames();
+        playlist.tracks[current].loop_points = (0, frames);
+        if playlist.decoding == current {
+            self.loop_points.set(0, frames);
+        }
+    }
 
-        al::alBufferData(
-            al_buffers[0],
-            sample_format,
-            samples.as_ptr() as *mut c_void,
-            (mem::size_of::<i16>() * samples.len()) as i32,
-            sample_rate,
+    /**
+     * Get the loop points of the Music.
+     *
+     * # Return
+     * Some((start, end)) in frames, the end being excluded, or None if the
+     * whole file is looped.
+     */
+    pub fn get_loop_points(&self) -> Option<(i64, i64)> {
+        let playlist = self.lock_playlist();
+        let track = &playlist.tracks[playlist.current];
+        match track.loop_points {
+            (0, end) if end == track.frames() => None,
+            points => Some(points),
+        }
+    }
+
+    /**
+     * Add a marker to the current track.
+     *
+     * The callback registered with `on_marker` is called each time the
+     * playback crosses the frame of the marker, on every pass of a loop.
+     * The cue points of WAV files are read as markers.
+     *
+     * # Arguments
+     * * `name` - The name of the marker
+     * * `frame` - The frame of the track the marker is at
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, AudioControllerExt, Music, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *     let mut msc = Music::new("path/to/song.ogg")?;
+     *     msc.add_marker("chorus", 44100 * 30);
+     *     msc.on_marker(|marker| println!("Reached the {}", marker.name));
+     *     msc.play();
+     *     Ok(())
+     * }
+     * ```
+     */
+    pub fn add_marker(&mut self, name: &str, frame: i64) -> () {
+        let mut playlist = self.lock_playlist();
+        let current = playlist.current;
+        markers::insert(
+            &mut playlist.tracks[current].markers,
+            Marker::new(name, frame),
         );
+    }
+
+    /**
+     * Get the markers of the current track.
+     *
+     * # Return
+     * The markers sorted by frame.
+     */
+    pub fn get_markers(&self) -> Vec<Marker> {
+        let playlist = self.lock_playlist();
+        playlist.tracks[playlist.current].markers.clone()
+    }
 
-        samples.clear();
+    /**
+     * Remove the markers of the current track, including its cue points.
+     */
+    pub fn clear_markers(&mut self) -> () {
+        let mut playlist = self.lock_playlist();
+        let current = playlist.current;
+        playlist.tracks[current].markers.clear();
+    }
 
-        fill_buffer(
-            &mut samples,
-            &mut self.file.as_mut().unwrap(),
-            self.cursor.clone(),
-            self.is_looping,
-        );
+    /**
+     * Set the musical clock of the Music.
+     *
+     * The clock gives the bar, beat and tick being played and lets changes
+     * be scheduled on the next beat or bar. It applies to every track of
+     * the playlist.
+     *
+     * # Argument
+     * * `clock` - The tempo, time signature and downbeat of the Music
+     */
+    pub fn set_beat_clock(&mut self, clock: BeatClock) -> () {
+        self.beat_clock = Some(clock);
+    }
 
-        al::alBufferData(
-            al_buffers[1],
-            sample_format,
-            samples.as_ptr() as *mut c_void,
-            (mem::size_of::<i16>() * samples.len()) as i32,
-            sample_rate,
-        );
+    /**
+     * Get the musical clock of the Music.
+     *
+     * # Return
+     * The clock set with set_beat_clock, None if there is none.
+     */
+    pub fn get_beat_clock(&self) -> Option<BeatClock> {
+        self.beat_clock
+    }
 
-        // Queue the buffers
-        al::alSourceQueueBuffers(al_source, 2, &al_buffers[0]);
+    /**
+     * Remove the musical clock of the Music.
+     *
+     * The changes already scheduled still happen.
+     */
+    pub fn clear_beat_clock(&mut self) -> () {
+        self.beat_clock = None;
+    }
 
-        // Start playing
-        al::alSourcePlay(al_source);
+    /**
+     * Get the musical position being played.
+     *
+     * # Return
+     * The bar, beat and tick of the current track being heard, None if the
+     * Music has no beat clock.
+     */
+    pub fn get_beat_position(&self) -> Option<BeatPosition> {
+        let clock = self.beat_clock?;
+        Some(clock.position_at(self.position()))
+    }
 
-        let (looping_sender, looping_receiver): (Sender<bool>, Receiver<bool>) = channel();
-        let (offset_sender, offset_receiver): (Sender<i32>, Receiver<i32>) = channel();
+    /**
+     * Get the time left until the next beat or bar.
+     *
+     * The time takes the pitch of the Music into account, it can be given
+     * to `PlayGroup::play_in` to start a Sound or another Music right on
+     * the beat.
+     *
+     * # Argument
+     * * `quantize` - Whether to wait for the next beat or bar
+     *
+     * # Return
+     * The time until the next beat or bar, None if the Music has no beat
+     * clock.
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, BeatClock, Music, PlayGroup, Quantize, Sound, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *     let mut msc = Music::new("path/to/theme.ogg")?;
+     *     let mut stinger = Sound::new("path/to/stinger.wav")?;
+     *     msc.set_beat_clock(BeatClock::new(120., 4, 4).expect("Invalid tempo"));
+     *     msc.play();
+     *
+     *     if let Some(delay) = msc.time_to_next(Quantize::Beat) {
+     *         PlayGroup::new().sound(&mut stinger).play_in(delay)?;
+     *     }
+     *     Ok(())
+     * }
+     * ```
+     */
+    pub fn time_to_next(&self, quantize: Quantize) -> Option<Duration> {
+        let clock = self.beat_clock?;
+        let now = self.position();
+        // The next beat is after the position, unless it is rounded before
+        let ahead = clock.next(now, quantize).saturating_sub(now);
+        let pitch = self.get_pitch();
+        if pitch > 0. {
+            Some(ahead.div_f32(pitch))
+        } else {
+            Some(ahead)
+        }
+    }
 
-        self.looping_sender = Some(looping_sender);
-        self.offset_sender = Some(offset_sender);
+    /**
+     * Call a function once the playback reaches the next beat or bar.
+     *
+     * The function is called from a background thread, a few milliseconds
+     * after the beat is heard, or right away if the playback loops or
+     * jumps back first. It is dropped if the Music is stopped.
+     *
+     * # Arguments
+     * * `quantize` - Whether to wait for the next beat or bar
+     * * `callback` - The function to call with the position reached
+     *
+     * # Return
+     * The position the function will be called at, None if the Music has
+     * no beat clock, in which case it is never called.
+     */
+    pub fn on_next<F>(&mut self, quantize: Quantize, callback: F) -> Option<BeatPosition>
+    where
+        F: FnOnce(BeatPosition) + Send + 'static,
+    {
+        self.schedule(quantize, BeatAction::Call(Box::new(callback)))
+    }
 
-        let cursor = self.cursor.clone();
-        let is_looping_clone = self.is_looping.clone();
-
-        let thread = thread::Builder::new().name(String::from("ears-music"));
-        self.thread_handle = Some(
-            thread
-                .spawn(move || {
-                    match OpenAlData::check_al_context() {
-                        Ok(_) => {}
-                        Err(err) => {
-                            println!("{}", err);
-                        }
-                    };
-                    let mut file: SndFile = port.recv().ok().unwrap();
-                    let mut status = ffi::AL_PLAYING;
-                    let mut buffers_processed = 0;
-                    let mut buffers_queued = 0;
-                    let mut buf = 0;
-                    let mut is_looping = is_looping_clone;
-                    let mut offset_shift_restart = false;
-
-                    while status != ffi::AL_STOPPED {
-                        // wait a bit
-                        sleep(Duration::from_millis(50));
-                        if status == ffi::AL_PLAYING {
-                            if let Ok(new_is_looping) = looping_receiver.try_recv() {
-                                is_looping = new_is_looping;
-                            }
-
-                            if let Ok(offset) = offset_receiver.try_recv() {
-                                // If we shift the offset, we need to stop and restart the source
-                                // so that we can swap out the buffers in an instantaneous manner
-                                al::alSourceStop(al_source);
-                                offset_shift_restart = true;
-                                cursor.store(offset.into(), Ordering::Relaxed);
-                            }
-
-                            al::alGetSourcei(
-                                al_source,
-                                ffi::AL_BUFFERS_QUEUED,
-                                &mut buffers_queued,
-                            );
-
-                            al::alGetSourcei(
-                                al_source,
-                                ffi::AL_BUFFERS_PROCESSED,
-                                &mut buffers_processed,
-                            );
-
-                            for _ in 0..buffers_processed {
-                                al::alSourceUnqueueBuffers(al_source, 1, &mut buf);
-
-                                samples.clear();
-
-                                fill_buffer(&mut samples, &mut file, cursor.clone(), is_looping);
-
-                                al::alBufferData(
-                                    buf,
-                                    sample_format,
-                                    samples.as_ptr() as *mut c_void,
-                                    (mem::size_of::<i16>() * samples.len()) as i32,
-                                    sample_rate,
-                                );
-                                al::alSourceQueueBuffers(al_source, 1, &buf);
-                            }
-
-                            // After buffer refill restart
-                            if offset_shift_restart {
-                                al::alSourcePlay(al_source);
-                                offset_shift_restart = false;
-                            }
-                        }
-                        // Get source status
-                        status = al::alGetState(al_source);
-                    }
-                    al::alSourcei(al_source, ffi::AL_BUFFER, 0);
-                })
-                .unwrap(),
+    /**
+     * Move to the next track of the playlist once the playback reaches the
+     * next beat or bar.
+     *
+     * The first samples of the next track are queued right after the beat
+     * or bar, like the tracks of the playlist follow each other. Like skip,
+     * the Music stops if the current track is the last one. The skip is
+     * cancelled if the Music is stopped, or moved past the beat or bar or
+     * to another track first.
+     *
+     * # Argument
+     * * `quantize` - Whether to wait for the next beat or bar
+     *
+     * # Return
+     * The position the track changes at, None if the Music has no beat
+     * clock, in which case nothing is scheduled.
+     */
+    pub fn skip_on_next(&mut self, quantize: Quantize) -> Option<BeatPosition> {
+        self.schedule(quantize, BeatAction::Skip)
+    }
+
+    // Queue a change for the streaming thread at the next beat or bar.
+    fn schedule(&mut self, quantize: Quantize, action: BeatAction) -> Option<BeatPosition> {
+        let clock = self.beat_clock?;
+        let offset = self.get_frame_offset();
+        let sample_rate = self.file_infos.samplerate as f64;
+        let next = clock.next(
+            Duration::from_secs_f64(offset as f64 / sample_rate),
+            quantize,
         );
-        let file = self.file.as_ref().unwrap().clone();
-        chan.send(*file);
+        let job = BeatJob {
+            track: self.get_current_track(),
+            frame: (next.as_secs_f64() * sample_rate).round() as i64,
+            scheduled: offset,
+            position: clock.position_at(next),
+            action: action,
+        };
+        let position = job.position;
+
+        lock_beat_jobs(&self.beat_jobs).push(job);
+        // Check the position more often from now on
+        streaming::wake();
+        Some(position)
+    }
+
+    /**
+     * Append a file to the playlist of the Music.
+     *
+     * The tracks of the playlist are played one after the other without
+     * any gap, the first samples of a track being queued right after the
+     * last ones of the previous track. A looping Music loops on its current
+     * track, skip moves to the next one.
+     *
+     * The file must have the same number of channels and sample rate as
+     * the file the Music was created with.
+     *
+     * # Argument
+     * * `path` - The path of the file to append
+     *
+     * # Return
+     * `Ok(())` on success, Err(SoundError) if the file can't be loaded or
+     * has another format.
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, Music, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   let mut msc = Music::new("path/to/first_part.ogg")?;
+     *   msc.append("path/to/second_part.ogg")?;
+     *   msc.append("path/to/third_part.ogg")?;
+     *   msc.play();
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn append(&mut self, path: &str) -> Result<(), SoundError> {
+        let track = Track::open(path)?;
+        let infos = track.file.get_sndinfo();
+        if infos.channels != self.file_infos.channels
+            || infos.samplerate != self.file_infos.samplerate
+        {
+            track.file.close();
+            return Err(SoundError::InvalidFormat);
+        }
+
+        self.lock_playlist().tracks.push(track);
+        Ok(())
+    }
+
+    /**
+     * Move to the next track of the playlist.
+     *
+     * The next track plays from its beginning right away, the Music stops
+     * if the current track is the last one.
+     */
+    pub fn skip(&mut self) -> () {
+        let next = self.get_current_track() + 1;
+        if next < self.get_track_count() {
+            self.set_track_offset(next, 0);
+        } else {
+            self.stop();
+        }
+    }
+
+    /**
+     * Remove the tracks waiting to be played from the playlist.
+     *
+     * A track whose first samples are already queued after the current one
+     * is kept, so the playlist still ends without a gap.
+     */
+    pub fn clear_playlist(&mut self) -> () {
+        let mut playlist = self.lock_playlist();
+        let last = playlist.current.max(playlist.decoding);
+        for track in playlist.tracks.drain(last + 1..) {
+            track.file.close();
+        }
+    }
+
+    /**
+     * Get the index of the track being played.
+     *
+     * # Return
+     * The index in the playlist, 0 being the file the Music was created
+     * with.
+     */
+    pub fn get_current_track(&self) -> usize {
+        self.lock_playlist().current
+    }
+
+    /**
+     * Get the number of tracks in the playlist.
+     *
+     * # Return
+     * The number of tracks, including the file the Music was created with.
+     */
+    pub fn get_track_count(&self) -> usize {
+        self.lock_playlist().tracks.len()
+    }
+
+    /**
+     * Get the tags of a track of the playlist.
+     *
+     * # Argument
+     * * `index` - The index of the track in the playlist
+     *
+     * # Return
+     * The tags of the track, None if there is no track at this index.
+     */
+    pub fn get_track_tags(&self, index: usize) -> Option<Tags> {
+        self.lock_playlist()
+            .tracks
+            .get(index)
+            .map(|track| track.tags.clone())
+    }
+
+    /**
+     * Crossfade from this Music to another one.
+     *
+     * The other Music starts, or resumes if it is paused, and fades in to
+     * its volume while this one fades out. Once the fade is over this Music
+     * is stopped and gets its volume back for its next play. The fade runs
+     * on the streaming thread, no call is needed while it goes on.
+     *
+     * # Arguments
+     * * `to` - The Music to fade in
+     * * `duration` - The duration of the crossfade
+     * * `curve` - The shape of the fade, EqualPower keeps the loudness
+     * steady between two different tracks
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, FadeCurve, Music};
+     * use std::time::Duration;
+     *
+     * let mut calm = Music::new("path/to/calm.ogg").unwrap();
+     * let mut battle = Music::new("path/to/battle.ogg").unwrap();
+     * calm.play();
+     *
+     * // The enemies are coming
+     * calm.crossfade(&mut battle, Duration::from_secs(2), FadeCurve::EqualPower);
+     * ```
+     */
+    pub fn crossfade(&mut self, to: &mut Music, duration: Duration, curve: FadeCurve) -> () {
+        check_openal_context!(());
+
+        // A Music already fading keeps the volume it is fading to
+        let out_volume = streaming::settled_value(self.al_source, ffi::AL_GAIN)
+            .unwrap_or_else(|| self.get_volume());
+        let in_volume =
+            streaming::settled_value(to.al_source, ffi::AL_GAIN).unwrap_or_else(|| to.get_volume());
+        if !to.is_playing() {
+            to.set_volume(0.);
+            to.play();
+        }
+
+        streaming::add_ramp(
+            Ramp::new(
+                self.al_source,
+                ffi::AL_GAIN,
+                self.get_volume(),
+                0.,
+                duration,
+                curve,
+            )
+            .then_stop(out_volume),
+        );
+        streaming::add_ramp(Ramp::new(
+            to.al_source,
+            ffi::AL_GAIN,
+            to.get_volume(),
+            in_volume,
+            duration,
+            curve,
+        ));
+    }
+
+    fn lock_playlist(&self) -> MutexGuard<'_, Playlist> {
+        lock(&self.playlist)
+    }
+
+    /// Move the playback to a frame of a track, right away if the Music is
+    /// streaming or from its next play otherwise.
+    fn set_track_offset(&mut self, track: usize, offset: i64) -> () {
+        let is_streaming = matches!(self.get_state(), Playing | Paused);
+
+        match self.offset_sender {
+            Some(ref sender) if is_streaming => {
+                sender.send((track, offset));
+                streaming::wake();
+            }
+            _ => {
+                let mut playlist = self.lock_playlist();
+                playlist.current = track;
+                playlist.decoding = track;
+                let (start, end) = playlist.tracks[track].loop_points;
+                self.loop_points.set(start, end);
+                self.loop_points.wrapped.store(false, Ordering::Relaxed);
+                self.cursor.store(offset, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Get the frame of the current track being played.
+    fn get_frame_offset(&self) -> i64 {
+        check_openal_context!(0);
+
+        let mut sample_offset: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut sample_offset);
+
+        let mut buffers_queued: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_BUFFERS_QUEUED, &mut buffers_queued);
+
+        let cursor = self.cursor.load(Ordering::Relaxed);
+        let buffer_frames = self.sample_to_read / self.file_infos.channels as i64;
+        let frames = {
+            let playlist = self.lock_playlist();
+            playlist.tracks[playlist.current].frames()
+        };
+
+        calculate_true_offset(
+            frames,
+            cursor,
+            buffer_frames,
+            buffers_queued,
+            sample_offset,
+            &self.loop_points,
+        )
+    }
+
+    /**
+     * Set the number of buffers queued while streaming the Music.
+     *
+     * More buffers make the Music more robust when the streaming thread
+     * is late, at the cost of memory. The new count is used from the next
+     * time the Music starts playing.
+     *
+     * The default is 2 buffers.
+     *
+     * # Argument
+     * * `count` - The number of buffers, at least 2
+     */
+    pub fn set_buffer_count(&mut self, count: i32) -> () {
+        self.buffer_count = count.max(2);
+    }
+
+    /**
+     * Get the number of buffers queued while streaming the Music.
+     *
+     * # Return
+     * The number of buffers used from the next play.
+     */
+    pub fn get_buffer_count(&self) -> i32 {
+        self.buffer_count
+    }
+
+    /**
+     * Set the duration of each buffer queued while streaming the Music.
+     *
+     * The buffers are refilled by the streaming thread as soon as they are
+     * played, shorter buffers lower the latency of set_offset and use less
+     * memory but leave less room for the thread to be late. The new
+     * duration is used from the next time the Music starts playing.
+     *
+     * The default is 1 second.
+     *
+     * # Argument
+     * * `duration` - The duration of each buffer, at least one frame
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, Music, SoundError};
+     * use std::time::Duration;
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   let mut msc = Music::new("path/to/music.ogg")?;
+     *   // 4 buffers of 250ms each
+     *   msc.set_buffer_count(4);
+     *   msc.set_buffer_duration(Duration::from_millis(250));
+     *   msc.play();
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn set_buffer_duration(&mut self, duration: Duration) -> () {
+        self.buffer_duration = duration;
+    }
+
+    /**
+     * Get the duration of each buffer queued while streaming the Music.
+     *
+     * # Return
+     * The duration of the buffers used from the next play.
+     */
+    pub fn get_buffer_duration(&self) -> Duration {
+        self.buffer_duration
+    }
+
+    /**
+     * Get the number of underruns of the Music.
+     *
+     * An underrun happens when all the queued buffers were played before
+     * the streaming thread refilled them, the Music is then restarted and
+     * a gap is heard. Raise the buffer count or duration if it happens.
+     *
+     * # Return
+     * The number of underruns since the Music was created.
+     */
+    pub fn get_underrun_count(&self) -> u32 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of frames held by each buffer.
+    fn buffer_frames(&self) -> i64 {
+        let frames =
+            self.buffer_duration.as_millis() as i64 * self.file_infos.samplerate as i64 / 1000;
+        frames.max(1)
+    }
+
+    /// Create the buffers again if their count changed.
+    fn update_buffers(&mut self) -> () {
+        if self.al_buffers.len() == self.buffer_count as usize {
+            return;
+        }
+
+        unsafe {
+            ffi::alDeleteBuffers(self.al_buffers.len() as i32, &mut self.al_buffers[0]);
+        }
+        self.al_buffers = vec![0; self.buffer_count as usize];
+        al::alGenBuffers(self.buffer_count, &mut self.al_buffers[0]);
+    }
+
+    // Queue the first buffers of a new stream, playing it is left to the
+    // caller.
+    fn prepare_stream(&mut self) -> MusicStream {
+        // Take the buffers back from the previous stream
+        streaming::remove(self.al_source);
+        al::alSourcei(self.al_source, ffi::AL_BUFFER, 0);
+        self.update_buffers();
+        self.sample_to_read = self.buffer_frames() * self.file_infos.channels as i64;
+
+        let (looping_sender, looping_receiver): (Sender<bool>, Receiver<bool>) = channel();
+        let (offset_sender, offset_receiver): (Sender<(usize, i64)>, Receiver<(usize, i64)>) =
+            channel();
+
+        self.looping_sender = Some(looping_sender);
+        self.offset_sender = Some(offset_sender);
+
+        // Refill the buffers at least twice per buffer duration
+        let poll_interval = (self.buffer_duration / 2)
+            .min(Duration::from_millis(MAX_POLL_INTERVAL_MS))
+            .max(Duration::from_millis(1));
+
+        let mut stream = MusicStream {
+            al_source: self.al_source,
+            playlist: self.playlist.clone(),
+            // create sample buffer and reserve the exact capacity we need
+            samples: Samples::with_capacity(self.float_samples, self.sample_to_read as usize),
+            sample_format: self.sample_format,
+            sample_rate: self.file_infos.samplerate,
+            cursor: self.cursor.clone(),
+            loop_points: self.loop_points.clone(),
+            is_looping: self.is_looping,
+            looping_receiver: looping_receiver,
+            offset_receiver: offset_receiver,
+            underruns: self.underruns.clone(),
+            poll_interval: poll_interval,
+            buffer_frames: self.buffer_frames(),
+            queued_tracks: VecDeque::new(),
+            status: ffi::AL_PLAYING,
+            marker_callback: self.marker_callback.clone(),
+            marker_tracker: MarkerTracker::new(self.cursor.load(Ordering::Relaxed)),
+            marker_track: 0,
+            beat_jobs: self.beat_jobs.clone(),
+            cut: None,
+        };
+
+        {
+            let playlist = self.playlist.clone();
+            let mut playlist = lock(&playlist);
+
+            // Restart reading from the current track
+            let current = playlist.current;
+            playlist.decoding = current;
+            stream.marker_track = current;
+            let (start, end) = playlist.tracks[current].loop_points;
+            self.loop_points.set(start, end);
+            self.loop_points.wrapped.store(false, Ordering::Relaxed);
+
+            for buffer in &self.al_buffers {
+                stream.queue(*buffer, &mut playlist);
+            }
+        }
+        stream
+    }
+}
+
+/**
+ * Get a Music ready to be played.
+ *
+ * A paused Music is left as is to be resumed, otherwise a new stream is
+ * prepared with its first buffers queued.
+ *
+ * # Return
+ * The OpenAL source to play and the stream to service once it plays, None
+ * if the Music is resumed.
+ */
+pub(crate) fn prepare_play(music: &mut Music) -> (u32, Option<Box<dyn Stream>>) {
+    let stream = match music.get_state() {
+        Paused => None,
+        _ => {
+            if music.is_playing() {
+                music.stop();
+            }
+            Some(Box::new(music.prepare_stream()) as Box<dyn Stream>)
+        }
+    };
+    (music.al_source, stream)
+}
+
+/**
+ * Get the layers of a LayeredMusic ready to be played together.
+ *
+ * Paused layers are left as is to be resumed, otherwise their streams are
+ * prepared and serviced as one. Every layer is read like the first one,
+ * with its buffers, offset and loop points, so their queues hold the same
+ * frames.
+ *
+ * # Return
+ * The OpenAL sources to play and the stream to service once they play,
+ * None if the layers are resumed.
+ */
+pub(crate) fn prepare_layers(
+    layers: &mut [Music],
+    offset_receiver: Receiver<i64>,
+) -> (Vec<u32>, Option<Box<dyn Stream>>) {
+    let sources: Vec<u32> = layers.iter().map(|layer| layer.al_source).collect();
+    if layers[0].get_state() == Paused {
+        return (sources, None);
+    }
+
+    let buffer_count = layers[0].buffer_count;
+    let buffer_duration = layers[0].buffer_duration;
+    let cursor = layers[0].cursor.load(Ordering::Relaxed);
+    let loop_points = {
+        let playlist = layers[0].lock_playlist();
+        playlist.tracks[playlist.current].loop_points
+    };
+
+    let streams = layers
+        .iter_mut()
+        .map(|layer| {
+            if layer.is_playing() {
+                layer.stop();
+            }
+            layer.buffer_count = buffer_count;
+            layer.buffer_duration = buffer_duration;
+            layer.cursor.store(cursor, Ordering::Relaxed);
+            {
+                let mut playlist = layer.lock_playlist();
+                let current = playlist.current;
+                playlist.tracks[current].loop_points = loop_points;
+            }
+            layer.prepare_stream()
+        })
+        .collect();
+    let stream = LayeredStream {
+        layers: streams,
+        offset_receiver: offset_receiver,
+    };
+    (sources, Some(Box::new(stream)))
+}
+
+/**
+ * Get the OpenAL identifier of the source of a Music.
+ *
+ * # Return
+ * The OpenAL internal identifier for the source of the music.
+ */
+pub(crate) fn get_source(music: &Music) -> u32 {
+    music.al_source
+}
+
+/**
+ * Get the infos of the file a Music was created with.
+ *
+ * # Return
+ * The struct SndInfo.
+ */
+pub(crate) fn get_sndinfo<'r>(music: &'r Music) -> &'r SndInfo {
+    &music.file_infos
+}
+
+fn lock(playlist: &Mutex<Playlist>) -> MutexGuard<'_, Playlist> {
+    match playlist.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn lock_beat_jobs(jobs: &Mutex<Vec<BeatJob>>) -> MutexGuard<'_, Vec<BeatJob>> {
+    match jobs.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// The state of a playing Music, refilled by the streaming thread.
+struct MusicStream {
+    al_source: u32,
+    playlist: Arc<Mutex<Playlist>>,
+    samples: Samples,
+    sample_format: i32,
+    sample_rate: i32,
+    cursor: Arc<AtomicI64>,
+    loop_points: Arc<LoopPoints>,
+    is_looping: bool,
+    looping_receiver: Receiver<bool>,
+    offset_receiver: Receiver<(usize, i64)>,
+    underruns: Arc<AtomicU32>,
+    poll_interval: Duration,
+    /// Number of frames of a full buffer
+    buffer_frames: i64,
+    /// Track of each queued buffer, in the order of the queue
+    queued_tracks: VecDeque<usize>,
+    /// State of the source at the last refill
+    status: i32,
+    marker_callback: MarkerCallback,
+    marker_tracker: MarkerTracker,
+    /// Track whose markers are followed
+    marker_track: usize,
+    beat_jobs: Arc<Mutex<Vec<BeatJob>>>,
+    /// Track and frame a skip scheduled on the beat clock moves to the next
+    /// track at
+    cut: Option<(usize, i64)>,
+}
+
+impl MusicStream {
+    /// Report the markers of the current track crossed since the last
+    /// service, at the position computed like get_offset.
+    fn report_markers(&mut self, playlist: &Playlist, callbacks: &mut Vec<Callback>) -> () {
+        // The previous track played to its end, maybe since the last service,
+        // and a new track is followed from its first frame
+        if playlist.current != self.marker_track {
+            if let Some(track) = playlist.tracks.get(self.marker_track) {
+                let end = self.track_end(self.marker_track, track);
+                for marker in self.marker_tracker.advance(&track.markers, end, None) {
+                    callbacks.push(markers::notify(&self.marker_callback, marker));
+                }
+            }
+            self.marker_track = playlist.current;
+            self.marker_tracker.jump(0);
+        }
+        let track = &playlist.tracks[playlist.current];
+        // The source stops by itself once it played the last frame, which
+        // may be since the last service
+        let position = if !self.is_looping && al::alGetState(self.al_source) == ffi::AL_STOPPED {
+            self.track_end(playlist.current, track)
+        } else {
+            self.playback_frame(playlist)
+        };
+        let loop_region = if self.is_looping {
+            Some(self.loop_points.get())
+        } else {
+            None
+        };
+
+        for marker in self
+            .marker_tracker
+            .advance(&track.markers, position, loop_region)
+        {
+            callbacks.push(markers::notify(&self.marker_callback, marker));
+        }
+    }
+
+    /// Get the last frame of a track played before the next one, the end
+    /// of the file unless a skip cut it.
+    fn track_end(&self, index: usize, track: &Track) -> i64 {
+        match self.cut {
+            Some((cut_track, frame)) if cut_track == index => (frame - 1).min(track.frames()),
+            _ => track.frames(),
+        }
+    }
+
+    /// Get the frame a skip of the track being decoded ends it at, the end
+    /// of the loop of a looping Music, the skip being heard once the loop
+    /// wraps.
+    fn cut_frame(&self, playlist: &Playlist) -> Option<i64> {
+        match self.cut {
+            Some((track, frame)) if track == playlist.decoding => {
+                if self.is_looping {
+                    Some(frame.min(self.loop_points.get().1))
+                } else {
+                    Some(frame)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the frame of the current track being played, like get_offset.
+    fn playback_frame(&self, playlist: &Playlist) -> i64 {
+        let mut sample_offset: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut sample_offset);
+        let mut buffers_queued: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_BUFFERS_QUEUED, &mut buffers_queued);
+
+        calculate_true_offset(
+            playlist.tracks[playlist.current].frames(),
+            self.cursor.load(Ordering::Relaxed),
+            self.buffer_frames,
+            buffers_queued,
+            sample_offset,
+            &self.loop_points,
+        )
+    }
+
+    /// Start the scheduled calls the playback reached and take the skips,
+    /// which are queued ahead.
+    ///
+    /// Return the frame of the first skip of the current track, if any.
+    fn run_beat_jobs(&mut self, playlist: &Playlist, callbacks: &mut Vec<Callback>) -> Option<i64> {
+        let mut jobs = lock_beat_jobs(&self.beat_jobs);
+        if jobs.is_empty() {
+            return None;
+        }
+
+        let position = self.playback_frame(playlist);
+        let current = playlist.current;
+        // The playback passed the frame, moved to another track or went
+        // back before the frame was reached
+        let (due, pending): (Vec<BeatJob>, Vec<BeatJob>) =
+            jobs.drain(..).partition(|job| match job.action {
+                BeatAction::Skip => true,
+                BeatAction::Call(_) => {
+                    job.track != current || position >= job.frame || position < job.scheduled
+                }
+            });
+        *jobs = pending;
+
+        let mut skip: Option<i64> = None;
+        for job in due {
+            match job.action {
+                BeatAction::Call(callback) => {
+                    let position = job.position;
+                    callbacks.push(Box::new(move || callback(position)));
+                }
+                // A skip is dropped if the track already changed
+                BeatAction::Skip if job.track == current => {
+                    skip = Some(skip.map_or(job.frame, |frame| frame.min(job.frame)));
+                }
+                BeatAction::Skip => {}
+            }
+        }
+        skip
+    }
+
+    /// Check if the samples of a frame of the current track are queued
+    /// already, being decoded before the playback reached them.
+    fn is_queued(&self, playlist: &Playlist, frame: i64, position: i64) -> bool {
+        let cursor = self.cursor.load(Ordering::Relaxed);
+        // The stream went on to the next track or wrapped around the loop
+        playlist.decoding != playlist.current || cursor < position || frame < cursor
+    }
+
+    /// Unqueue the played buffers and queue them again with the next
+    /// samples.
+    ///
+    /// Return the number of buffers played and whether new samples were
+    /// queued.
+    fn refill(&mut self, playlist: &mut Playlist) -> (i32, bool) {
+        let mut buffers_processed = 0;
+        al::alGetSourcei(
+            self.al_source,
+            ffi::AL_BUFFERS_PROCESSED,
+            &mut buffers_processed,
+        );
+
+        let mut buf = 0;
+        let mut refilled = false;
+        for _ in 0..buffers_processed {
+            al::alSourceUnqueueBuffers(self.al_source, 1, &mut buf);
+            self.queued_tracks.pop_front();
+
+            self.queue(buf, playlist);
+            refilled |= self.samples.len() > 0;
+        }
+
+        // The track of the buffer being played is the one heard
+        if let Some(track) = self.queued_tracks.front() {
+            playlist.current = *track;
+        }
+        (buffers_processed, refilled)
+    }
+
+    /// Stop the source to play a track from a frame, the buffers being
+    /// refilled before it restarts.
+    fn jump(&mut self, playlist: &mut Playlist, track: usize, offset: i64) -> () {
+        al::alSourceStop(self.al_source);
+        playlist.current = track;
+        playlist.decoding = track;
+        let (start, end) = playlist.tracks[track].loop_points;
+        self.loop_points.set(start, end);
+        self.loop_points.wrapped.store(false, Ordering::Relaxed);
+        self.cursor.store(offset, Ordering::Relaxed);
+        self.marker_track = track;
+        self.marker_tracker.jump(offset);
+    }
+
+    /// Fill a buffer with the next samples of the playlist and queue it.
+    ///
+    /// A buffer never holds the samples of two tracks, the next track is
+    /// read once the previous one is fully queued, or queued up to the
+    /// frame of a skip.
+    fn queue(&mut self, buffer: u32, playlist: &mut Playlist) -> () {
+        self.samples.clear();
+
+        let cut = self.cut_frame(playlist);
+        fill_buffer(
+            &mut self.samples,
+            &mut playlist.tracks[playlist.decoding].file,
+            self.cursor.clone(),
+            self.is_looping,
+            &self.loop_points,
+            cut,
+        );
+
+        // The frame the track ends at is kept once reached, for the markers
+        // and the next refills
+        let at_cut = match cut {
+            Some(frame) if self.cursor.load(Ordering::Relaxed) >= frame => {
+                self.cut = Some((playlist.decoding, frame));
+                true
+            }
+            _ => false,
+        };
+
+        if self.samples.len() == 0
+            && (!self.is_looping || at_cut)
+            && playlist.decoding + 1 < playlist.tracks.len()
+        {
+            playlist.decoding += 1;
+            let (start, end) = playlist.tracks[playlist.decoding].loop_points;
+            self.loop_points.set(start, end);
+            self.loop_points.wrapped.store(false, Ordering::Relaxed);
+            self.cursor.store(0, Ordering::Relaxed);
+
+            fill_buffer(
+                &mut self.samples,
+                &mut playlist.tracks[playlist.decoding].file,
+                self.cursor.clone(),
+                self.is_looping,
+                &self.loop_points,
+                None,
+            );
+        }
+
+        self.samples
+            .upload(buffer, self.sample_format, self.sample_rate);
+        al::alSourceQueueBuffers(self.al_source, 1, &buffer);
+        self.queued_tracks.push_back(playlist.decoding);
+    }
+}
+
+impl Stream for MusicStream {
+    fn source(&self) -> u32 {
+        self.al_source
+    }
+
+    fn poll_interval(&self) -> Duration {
+        match self.wake_interval() {
+            Some(interval) => interval.min(self.poll_interval),
+            None => self.poll_interval,
+        }
+    }
+
+    fn wake_interval(&self) -> Option<Duration> {
+        // A paused Music has nothing to follow until it is resumed
+        if self.status == ffi::AL_PAUSED {
+            return None;
+        }
+        if markers::has_callback(&self.marker_callback)
+            || !lock_beat_jobs(&self.beat_jobs).is_empty()
+        {
+            Some(Duration::from_millis(markers::MARKER_INTERVAL_MS))
+        } else {
+            None
+        }
+    }
+
+    fn service(&mut self, callbacks: &mut Vec<Callback>) -> bool {
+        let al_source = self.al_source;
+        let mut buffers_queued = 0;
+        let mut offset_shift_restart = false;
+
+        if self.status == ffi::AL_PLAYING {
+            let playlist = self.playlist.clone();
+            let mut playlist = lock(&playlist);
+
+            if let Ok(new_is_looping) = self.looping_receiver.try_recv() {
+                self.is_looping = new_is_looping;
+            }
+
+            if let Ok((track, offset)) = self.offset_receiver.try_recv() {
+                if track < playlist.tracks.len() {
+                    // If we shift the offset, we need to stop and restart the source
+                    // so that we can swap out the buffers in an instantaneous manner
+                    self.jump(&mut playlist, track, offset);
+                    offset_shift_restart = true;
+
+                    // A skip is cancelled by moving past it or to another track
+                    if let Some((cut_track, frame)) = self.cut {
+                        if cut_track != track || frame <= offset {
+                            self.cut = None;
+                        }
+                    }
+                }
+            }
+
+            if let Some(frame) = self.run_beat_jobs(&playlist, callbacks) {
+                let current = playlist.current;
+                let position = self.playback_frame(&playlist);
+                let earlier = match self.cut {
+                    Some((track, cut)) => track != current || frame < cut,
+                    None => true,
+                };
+
+                if frame <= position {
+                    // The playback reached the frame before the skip was
+                    // queued, move to the next track right away
+                    let next = current + 1;
+                    if next < playlist.tracks.len() {
+                        self.jump(&mut playlist, next, 0);
+                        offset_shift_restart = true;
+                    } else {
+                        // Skipping the last track stops the Music
+                        al::alSourceStop(al_source);
+                        al::alSourcei(al_source, ffi::AL_BUFFER, 0);
+                        lock_beat_jobs(&self.beat_jobs).clear();
+                        return false;
+                    }
+                } else if earlier {
+                    self.cut = Some((current, frame));
+                    // The buffers holding the frame are queued again from
+                    // the frame being played, cut this time
+                    if self.is_queued(&playlist, frame, position) {
+                        self.jump(&mut playlist, current, position);
+                        offset_shift_restart = true;
+                    }
+                }
+            }
+
+            al::alGetSourcei(al_source, ffi::AL_BUFFERS_QUEUED, &mut buffers_queued);
+
+            let (buffers_processed, refilled) = self.refill(&mut playlist);
+
+            // The source stops by itself when it played all its buffers
+            // before they were refilled, restart it if there is more to play
+            if !offset_shift_restart
+                && refilled
+                && buffers_processed == buffers_queued
+                && al::alGetState(al_source) == ffi::AL_STOPPED
+            {
+                self.underruns.fetch_add(1, Ordering::Relaxed);
+                al::alSourcePlay(al_source);
+            }
+
+            // After buffer refill restart
+            if offset_shift_restart {
+                al::alSourcePlay(al_source);
+            }
+
+            if markers::has_callback(&self.marker_callback) {
+                self.report_markers(&playlist, callbacks);
+            }
+        }
+
+        // Get source status
+        self.status = al::alGetState(al_source);
+        if self.status == ffi::AL_STOPPED {
+            al::alSourcei(al_source, ffi::AL_BUFFER, 0);
+            lock_beat_jobs(&self.beat_jobs).clear();
+            return false;
+        }
+        true
+    }
+}
+
+/// The streams of the layers of a LayeredMusic, refilled together from the
+/// cursor of the first one and restarted together so they stay on the same
+/// sample.
+struct LayeredStream {
+    layers: Vec<MusicStream>,
+    offset_receiver: Receiver<i64>,
+}
+
+impl LayeredStream {
+    fn sources(&self) -> Vec<u32> {
+        self.layers.iter().map(|layer| layer.al_source).collect()
+    }
+
+    /// Unqueue the buffers played by every layer and queue them again with
+    /// the next frames, every layer reading the frames the first one reads
+    /// so their queues stay the same.
+    ///
+    /// Return whether new samples were queued.
+    fn refill(&mut self) -> bool {
+        let buffers_processed = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut processed = 0;
+                al::alGetSourcei(layer.al_source, ffi::AL_BUFFERS_PROCESSED, &mut processed);
+                processed
+            })
+            .min()
+            .unwrap_or(0);
+
+        let mut refilled = false;
+        for _ in 0..buffers_processed {
+            let cursor = self.layers[0].cursor.load(Ordering::Relaxed);
+            let (start, end) = self.layers[0].loop_points.get();
+            let is_looping = self.layers[0].is_looping;
+
+            for layer in &mut self.layers {
+                layer.cursor.store(cursor, Ordering::Relaxed);
+                layer.loop_points.set(start, end);
+                layer.is_looping = is_looping;
+
+                let mut buf = 0;
+                al::alSourceUnqueueBuffers(layer.al_source, 1, &mut buf);
+                layer.queued_tracks.pop_front();
+
+                let playlist = layer.playlist.clone();
+                let mut playlist = lock(&playlist);
+                layer.queue(buf, &mut playlist);
+                refilled |= layer.samples.len() > 0;
+            }
+        }
+        refilled
+    }
+
+    /// Move every layer to a frame and start them together.
+    fn seek(&mut self, offset: i64) -> () {
+        for layer in &mut self.layers {
+            let playlist = layer.playlist.clone();
+            let mut playlist = lock(&playlist);
+            let current = playlist.current;
+            layer.jump(&mut playlist, current, offset);
+        }
+        self.refill();
+        al::alSourcePlayv(&self.sources());
+    }
+}
+
+impl Stream for LayeredStream {
+    fn source(&self) -> u32 {
+        self.layers[0].al_source
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.layers
+            .iter()
+            .map(|layer| layer.poll_interval())
+            .min()
+            .unwrap_or(Duration::from_millis(MAX_POLL_INTERVAL_MS))
+    }
+
+    fn wake_interval(&self) -> Option<Duration> {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.wake_interval())
+            .min()
+    }
+
+    fn service(&mut self, callbacks: &mut Vec<Callback>) -> bool {
+        if self.layers[0].status == ffi::AL_PLAYING {
+            for layer in &mut self.layers {
+                if let Ok(new_is_looping) = layer.looping_receiver.try_recv() {
+                    layer.is_looping = new_is_looping;
+                }
+            }
+
+            // Offsets are applied once the layers play, like the offset of a
+            // Music
+            if let Ok(offset) = self.offset_receiver.try_recv() {
+                self.seek(offset);
+            } else {
+                // The first layer played all its buffers before they were
+                // refilled. The others are stopped as well, so every buffer
+                // is refilled and the layers restart together from the same
+                // queue.
+                let underrun = al::alGetState(self.layers[0].al_source) == ffi::AL_STOPPED;
+                if underrun {
+                    for layer in &self.layers[1..] {
+                        al::alSourceStop(layer.al_source);
+                    }
+                }
+
+                if self.refill() && underrun {
+                    self.layers[0].underruns.fetch_add(1, Ordering::Relaxed);
+                    al::alSourcePlayv(&self.sources());
+                }
+            }
+
+            for layer in &mut self.layers {
+                if markers::has_callback(&layer.marker_callback) {
+                    let playlist = layer.playlist.clone();
+                    let playlist = lock(&playlist);
+                    layer.report_markers(&playlist, callbacks);
+                }
+            }
+        }
+
+        for layer in &mut self.layers {
+            layer.status = al::alGetState(layer.al_source);
+        }
+        if self.layers[0].status == ffi::AL_STOPPED {
+            for layer in &self.layers {
+                al::alSourcei(layer.al_source, ffi::AL_BUFFER, 0);
+                lock_beat_jobs(&layer.beat_jobs).clear();
+            }
+            return false;
+        }
+        true
     }
 }
 
@@ -417,20 +1830,11 @@ impl AudioController for Music {
     fn play(&mut self) -> () {
         check_openal_context!(());
 
-        match self.get_state() {
-            Paused => {
-                al::alSourcePlay(self.al_source);
-                return;
-            }
-            _ => {
-                if self.is_playing() {
-                    al::alSourceStop(self.al_source);
-                    // wait a bit for openal terminate
-                    sleep(Duration::from_millis(50));
-                }
-                self.file.as_mut().unwrap().seek(0, SeekSet);
-                self.process_music();
-            }
+        let (source, stream) = prepare_play(self);
+        al::alSourcePlay(source);
+        streaming::started(source);
+        if let Some(stream) = stream {
+            streaming::add(stream);
         }
     }
 
@@ -449,7 +1853,38 @@ impl AudioController for Music {
     fn stop(&mut self) -> () {
         check_openal_context!(());
 
+        streaming::cancel_ramps(self.al_source);
+        streaming::remove(self.al_source);
         al::alSourceStop(self.al_source);
+        lock_beat_jobs(&self.beat_jobs).clear();
+    }
+
+    /**
+     * Fade the volume of the Music out, then stop it.
+     *
+     * The volume goes back to its value before the fade once the Music is
+     * stopped.
+     *
+     * # Argument
+     * * `duration` - The duration of the fade
+     */
+    fn stop_with_fade(&mut self, duration: Duration) -> () {
+        check_openal_context!(());
+
+        // Fading out again keeps the volume the first fade started from
+        let volume = streaming::settled_value(self.al_source, ffi::AL_GAIN)
+            .unwrap_or_else(|| self.get_volume());
+        streaming::add_ramp(
+            Ramp::new(
+                self.al_source,
+                ffi::AL_GAIN,
+                self.get_volume(),
+                0.,
+                duration,
+                FadeCurve::Linear,
+            )
+            .then_stop(volume),
+        );
     }
 
     /**
@@ -520,12 +1955,8 @@ impl AudioController for Music {
      * * `offset` - The frame to seek to
      */
     fn set_offset(&mut self, offset: i32) -> () {
-        match self.offset_sender {
-            Some(ref sender) => {
-                sender.send(offset);
-            }
-            None => self.cursor.store(offset.into(), Ordering::Relaxed),
-        }
+        let current = self.get_current_track();
+        self.set_track_offset(current, offset.into());
     }
 
     /**
@@ -535,24 +1966,7 @@ impl AudioController for Music {
      * The current frame being played
      */
     fn get_offset(&self) -> i32 {
-        check_openal_context!(0);
-
-        let mut sample_offset: i32 = 0;
-        al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut sample_offset);
-
-        let mut buffers_queued: i32 = 0;
-        al::alGetSourcei(self.al_source, ffi::AL_BUFFERS_QUEUED, &mut buffers_queued);
-
-        let cursor = self.cursor.load(Ordering::Relaxed);
-        let buffer_size = self.sample_to_read;
-
-        calculate_true_offset(
-            &self.file_infos,
-            cursor,
-            buffer_size,
-            buffers_queued,
-            sample_offset,
-        )
+        self.get_frame_offset().try_into().unwrap_or(i32::MAX)
     }
 
     /**
@@ -568,6 +1982,7 @@ impl AudioController for Music {
     fn set_volume(&mut self, volume: f32) -> () {
         check_openal_context!(());
 
+        streaming::cancel_ramp(self.al_source, ffi::AL_GAIN);
         al::alSourcef(self.al_source, ffi::AL_GAIN, volume);
     }
 
@@ -585,6 +2000,27 @@ impl AudioController for Music {
         volume
     }
 
+    /**
+     * Change the volume of the Music progressively.
+     *
+     * # Arguments
+     * * `volume` - The volume to reach, should be between 0.0 and 1.0
+     * * `duration` - The duration of the fade
+     * * `curve` - The shape of the fade
+     */
+    fn fade_to(&mut self, volume: f32, duration: Duration, curve: FadeCurve) -> () {
+        check_openal_context!(());
+
+        streaming::add_ramp(Ramp::new(
+            self.al_source,
+            ffi::AL_GAIN,
+            self.get_volume(),
+            volume,
+            duration,
+            curve,
+        ));
+    }
+
     /**
      * Set the minimal volume for a Music.
      *
@@ -656,6 +2092,7 @@ impl AudioController for Music {
     fn set_looping(&mut self, looping: bool) -> () {
         if let Some(ref sender) = self.looping_sender {
             sender.send(looping);
+            streaming::wake();
         }
         self.is_looping = looping;
     }
@@ -683,6 +2120,7 @@ impl AudioController for Music {
     fn set_pitch(&mut self, pitch: f32) -> () {
         check_openal_context!(());
 
+        streaming::cancel_ramp(self.al_source, ffi::AL_PITCH);
         al::alSourcef(self.al_source, ffi::AL_PITCH, pitch)
     }
 
@@ -700,6 +2138,27 @@ impl AudioController for Music {
         pitch
     }
 
+    /**
+     * Change the pitch of the Music progressively.
+     *
+     * # Arguments
+     * * `pitch` - The pitch to reach in the range [0.5 - 2.0]
+     * * `duration` - The duration of the change
+     * * `curve` - The shape of the change
+     */
+    fn pitch_to(&mut self, pitch: f32, duration: Duration, curve: FadeCurve) -> () {
+        check_openal_context!(());
+
+        streaming::add_ramp(Ramp::new(
+            self.al_source,
+            ffi::AL_PITCH,
+            self.get_pitch(),
+            pitch,
+            duration,
+            curve,
+        ));
+    }
+
     /**
      * Set the position of the Music relative to the listener or absolute.
      *
@@ -983,16 +2442,100 @@ impl AudioController for Music {
     }
 }
 
+impl AudioControllerExt for Music {
+    /**
+     * Call a function once the Music stops.
+     *
+     * # Argument
+     * * `callback` - The function to call, from a background thread
+     */
+    fn on_finished<F>(&mut self, callback: F) -> ()
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        check_openal_context!(());
+
+        streaming::watch(self.al_source, Box::new(callback));
+    }
+
+    /**
+     * Get a channel receiving a message once the Music stops.
+     *
+     * # Return
+     * The Receiver end of the channel.
+     */
+    fn finished(&mut self) -> Receiver<()> {
+        let (sender, receiver) = channel();
+        self.on_finished(move || {
+            let _ = sender.send(());
+        });
+        receiver
+    }
+
+    /**
+     * Call a function each time the playback crosses a marker of the
+     * current track.
+     *
+     * # Argument
+     * * `callback` - The function to call with the crossed marker, from a
+     * background thread
+     */
+    fn on_marker<F>(&mut self, callback: F) -> ()
+    where
+        F: FnMut(&Marker) + Send + 'static,
+    {
+        markers::set_callback(&self.marker_callback, Box::new(callback));
+        // Check the position more often from now on
+        streaming::wake();
+    }
+
+    /**
+     * Move the playback of the current track to a time.
+     *
+     * # Argument
+     * * `position` - The time from the start of the track
+     */
+    fn seek(&mut self, position: Duration) -> () {
+        let frame = position.as_secs_f64() * self.file_infos.samplerate as f64;
+        let current = self.get_current_track();
+        self.set_track_offset(current, frame.round() as i64);
+    }
+
+    /**
+     * Get the time of the current track being heard.
+     *
+     * # Return
+     * The time from the start of the track
+     */
+    fn position(&self) -> Duration {
+        check_openal_context!(Duration::from_secs(0));
+
+        let mut seconds = self.get_frame_offset() as f64 / self.file_infos.samplerate as f64;
+
+        // The samples at the offset are heard once the latency elapsed
+        let mut values = [0.; 2];
+        if al::alGetState(self.al_source) == ffi::AL_PLAYING
+            && al::alGetSourcedvSOFT(self.al_source, ffi::AL_SEC_OFFSET_LATENCY_SOFT, &mut values)
+        {
+            seconds -= values[1];
+        }
+        Duration::from_secs_f64(seconds.max(0.))
+    }
+}
+
 impl Drop for Music {
     /// Destroy all the resources of the Music.
     fn drop(&mut self) -> () {
+        // Forgotten before the stop, which would call them
+        streaming::unwatch(self.al_source);
+        streaming::remove(self.al_source);
         self.stop();
-        if let Some(handle) = self.thread_handle.take() {
-            handle.join();
+        for track in &self.lock_playlist().tracks {
+            track.file.close();
         }
         unsafe {
             al::alSourcei(self.al_source, ffi::AL_BUFFER, 0);
-            ffi::alDeleteBuffers(2, &mut self.al_buffers[0]);
+            ffi::alDeleteBuffers(self.al_buffers.len() as i32, &mut self.al_buffers[0]);
             ffi::alDeleteSources(1, &mut self.al_source);
         }
     }
@@ -1002,61 +2545,104 @@ impl Drop for Music {
 mod test {
     #![allow(non_snake_case)]
 
-    use audio_controller::AudioController;
+    use audio_controller::{AudioController, AudioControllerExt};
+    use beat_clock::{BeatClock, Quantize};
+    use fade;
+    use fade::FadeCurve;
+    use headless;
+    use markers::Marker;
     use music::Music;
     use states::State::{Paused, Playing, Stopped};
+    use std::fs;
+    use std::io::BufReader;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
 
     #[test]
-    #[ignore]
     fn music_create_OK() -> () {
-        let msc = Music::new("res/shot.wav");
+        let _harness = headless::lock();
+
+        let msc = Music::new("res/explosion.wav");
 
         assert!(msc.is_ok());
     }
 
+    #[test]
+    fn music_from_bytes_OK() -> () {
+        let mut harness = headless::lock();
+
+        let bytes = fs::read("res/explosion.wav").expect("Cannot read file");
+        let mut msc = Music::from_bytes(bytes).expect("Cannot create Music");
+
+        msc.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        msc.stop();
+    }
+
+    #[test]
+    fn music_from_reader_OK() -> () {
+        let mut harness = headless::lock();
+
+        let file = fs::File::open("res/explosion.wav").expect("Cannot open file");
+        let mut msc = Music::from_reader(BufReader::new(file)).expect("Cannot create Music");
+
+        msc.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        msc.stop();
+    }
+
     #[test]
     fn music_create_FAIL() -> () {
+        let _harness = headless::lock();
+
         let msc = Music::new("toto.wav");
 
         assert!(msc.is_err());
     }
 
     #[test]
-    #[ignore]
     fn music_play_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let mut harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.play();
         assert_eq!(msc.get_state() as i32, Playing as i32);
+        assert!(!headless::is_silent(&harness.render_short()));
         msc.stop();
     }
 
     #[test]
-    #[ignore]
     fn music_pause_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let mut harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.play();
         msc.pause();
         assert_eq!(msc.get_state() as i32, Paused as i32);
+        assert!(headless::is_silent(&harness.render_short()));
         msc.stop();
     }
 
     #[test]
-    #[ignore]
     fn music_stop_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let mut harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.play();
         msc.stop();
         assert_eq!(msc.get_state() as i32, Stopped as i32);
+        assert!(headless::is_silent(&harness.render_short()));
         msc.stop();
     }
 
     #[test]
-    #[ignore]
     fn music_is_playing_TRUE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.play();
         assert_eq!(msc.is_playing(), true);
@@ -1064,81 +2650,90 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn music_is_playing_FALSE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         assert_eq!(msc.is_playing(), false);
         msc.stop();
     }
 
     #[test]
-    #[ignore]
     fn music_set_volume_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_volume(0.7);
         assert_eq!(msc.get_volume(), 0.7);
     }
 
     #[test]
-    #[ignore]
     fn music_set_min_volume_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_min_volume(0.1);
         assert_eq!(msc.get_min_volume(), 0.1);
     }
 
     #[test]
-    #[ignore]
     fn music_set_max_volume_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_max_volume(0.9);
         assert_eq!(msc.get_max_volume(), 0.9);
     }
 
     #[test]
-    #[ignore]
     fn music_is_looping_TRUE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_looping(true);
         assert_eq!(msc.is_looping(), true);
     }
 
     #[test]
-    #[ignore]
     fn music_is_looping_FALSE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_looping(false);
         assert_eq!(msc.is_looping(), false);
     }
 
     #[test]
-    #[ignore]
     fn music_set_pitch_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_pitch(1.5);
         assert_eq!(msc.get_pitch(), 1.5);
     }
 
     #[test]
-    #[ignore]
     fn music_set_relative_TRUE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_relative(true);
         assert_eq!(msc.is_relative(), true);
     }
 
     #[test]
-    #[ignore]
     fn music_set_relative_FALSE() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_relative(false);
         assert_eq!(msc.is_relative(), false);
@@ -1147,9 +2742,10 @@ mod test {
     // untill https://github.com/rust-lang/rust/issues/7622 is not fixed, slice comparsion is used
 
     #[test]
-    #[ignore]
     fn music_set_position_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_position([50., 150., 250.]);
         let res = msc.get_position();
@@ -1157,9 +2753,10 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn music_set_direction_OK() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_direction([50., 150., 250.]);
         let res = msc.get_direction();
@@ -1167,27 +2764,418 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn music_set_max_distance() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_max_distance(70.);
         assert_eq!(msc.get_max_distance(), 70.);
     }
 
     #[test]
-    #[ignore]
     fn music_set_reference_distance() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_reference_distance(70.);
         assert_eq!(msc.get_reference_distance(), 70.);
     }
 
     #[test]
-    #[ignore]
+    fn music_loop_points_from_file_OK() -> () {
+        let _harness = headless::lock();
+
+        let wav = headless::wav_with_loop(&[0; 4410], 1000, 2000);
+        let msc = Music::from_bytes(wav).expect("Cannot create Music");
+        let (start, end) = msc.get_loop_points().expect("No loop points");
+
+        assert_eq!(start, 1000);
+        assert_eq!(end, 2000);
+    }
+
+    #[test]
+    fn music_loop_points_OK() -> () {
+        let mut harness = headless::lock();
+
+        // A short click followed by silence, looping on the silence only
+        let samples: Vec<i16> = (0..4410).map(|i| if i < 441 { 10000 } else { 0 }).collect();
+        let mut msc = Music::from_bytes(headless::wav_with_loop(&samples, 0, 4410))
+            .expect("Cannot create Music");
+        msc.set_loop_points(441, 4410).unwrap();
+        assert_eq!(msc.get_loop_points(), Some((441, 4410)));
+
+        msc.set_looping(true);
+        msc.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        assert!(headless::is_silent(
+            &harness.render(Duration::from_millis(300))
+        ));
+        assert!(msc.is_playing());
+        msc.stop();
+    }
+
+    #[test]
+    fn music_short_loop_OK() -> () {
+        let mut harness = headless::lock();
+
+        // Silence then a loop of a single frame, read many times to fill
+        // each buffer
+        let samples: Vec<i16> = (0..4410)
+            .map(|i| if i == 4000 { 10000 } else { 0 })
+            .collect();
+        let mut msc = Music::from_bytes(headless::wav_with_loop(&samples, 4000, 4001))
+            .expect("Cannot create Music");
+        assert_eq!(msc.get_loop_points(), Some((4000, 4001)));
+
+        msc.set_looping(true);
+        msc.play();
+        harness.render_short();
+        let samples = harness.render(Duration::from_millis(300));
+        assert!(samples.iter().all(|sample| *sample != 0));
+        assert!(msc.is_playing());
+        msc.stop();
+    }
+
+    #[test]
+    fn music_loop_points_FAIL() -> () {
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+
+        assert!(msc.set_loop_points(2000, 1000).is_err());
+        assert!(msc.set_loop_points(0, 300000).is_err());
+        assert_eq!(msc.get_loop_points(), None);
+
+        msc.set_loop_points(1000, 2000).unwrap();
+        msc.clear_loop_points();
+        assert_eq!(msc.get_loop_points(), None);
+    }
+
+    #[test]
+    fn music_buffering_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        assert_eq!(msc.get_buffer_count(), 2);
+
+        msc.set_buffer_count(4);
+        msc.set_buffer_duration(Duration::from_millis(200));
+        assert_eq!(msc.get_buffer_count(), 4);
+        assert_eq!(msc.get_buffer_duration(), Duration::from_millis(200));
+
+        msc.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        assert_eq!(msc.get_underrun_count(), 0);
+        msc.stop();
+
+        msc.set_buffer_count(1);
+        assert_eq!(msc.get_buffer_count(), 2);
+    }
+
+    #[test]
+    fn music_underrun_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        msc.set_buffer_duration(Duration::from_millis(1));
+        msc.set_looping(true);
+        msc.play();
+
+        // The buffers are played faster than the refills between two
+        // rendered chunks
+        harness.render_short();
+
+        assert!(msc.get_underrun_count() > 0);
+        assert!(msc.is_playing());
+        msc.stop();
+    }
+
+    #[test]
+    fn music_render_long_OK() -> () {
+        let mut harness = headless::lock();
+
+        // Much longer than the queued buffers, refilled while rendering
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        msc.set_buffer_duration(Duration::from_millis(100));
+        msc.play();
+
+        let samples = harness.render(Duration::from_secs(2));
+        let tail = &samples[samples.len() - 4410 * 2..];
+        assert!(!headless::is_silent(tail));
+        assert!(msc.is_playing());
+        assert_eq!(msc.get_underrun_count(), 0);
+        msc.stop();
+    }
+
+    #[test]
+    fn music_playlist_gapless_OK() -> () {
+        let mut harness = headless::lock();
+
+        let first = headless::TempWav::new(&[10000; 4410]);
+        let second = headless::TempWav::new(&[-10000; 4410]);
+        let mut msc = Music::new(first.path()).expect("Cannot create Music");
+        msc.append(second.path()).unwrap();
+        assert_eq!(msc.get_track_count(), 2);
+        assert!(msc.get_track_tags(1).is_some());
+        assert!(msc.get_track_tags(2).is_none());
+
+        msc.play();
+        assert_eq!(msc.get_current_track(), 0);
+
+        // No silence between the two tracks, past the start of the mix
+        let samples = harness.render(Duration::from_millis(150));
+        assert!(samples[256..8000 * 2].iter().all(|sample| *sample != 0));
+        assert_eq!(msc.get_current_track(), 1);
+
+        harness.render_short();
+        assert!(!msc.is_playing());
+    }
+
+    #[test]
+    fn music_playlist_skip_OK() -> () {
+        let mut harness = headless::lock();
+
+        let silence = headless::TempWav::new(&[0; 44100]);
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        msc.append(silence.path()).unwrap_err();
+
+        let mut msc = Music::new(silence.path()).expect("Cannot create Music");
+        let click = headless::TempWav::new(&[10000; 44100]);
+        msc.append(click.path()).unwrap();
+        msc.set_looping(true);
+        msc.play();
+        assert!(headless::is_silent(&harness.render_short()));
+
+        msc.skip();
+        assert!(!headless::is_silent(&harness.render_short()));
+        assert_eq!(msc.get_current_track(), 1);
+
+        msc.skip();
+        assert!(!msc.is_playing());
+    }
+
+    #[test]
+    fn music_clear_playlist_OK() -> () {
+        let _harness = headless::lock();
+
+        let silence = headless::TempWav::new(&[0; 4410]);
+        let mut msc = Music::new(silence.path()).expect("Cannot create Music");
+        msc.append(silence.path()).unwrap();
+        msc.append(silence.path()).unwrap();
+        assert_eq!(msc.get_track_count(), 3);
+
+        msc.clear_playlist();
+        assert_eq!(msc.get_track_count(), 1);
+        assert_eq!(msc.get_current_track(), 0);
+    }
+
+    #[test]
+    fn music_finished_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc =
+            Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        msc.play();
+        let finished = msc.finished();
+        assert!(finished.try_recv().is_err());
+
+        harness.render(Duration::from_millis(200));
+        assert!(finished.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert_eq!(msc.get_state(), Stopped);
+    }
+
+    #[test]
+    fn music_on_marker_loop_OK() -> () {
+        let mut harness = headless::lock();
+
+        let wav = headless::wav_with_cues(&[0; 4410], &[(1, 2205)]);
+        let mut msc = Music::from_bytes(wav).expect("Cannot create Music");
+        assert_eq!(msc.get_markers(), vec![Marker::new("1", 2205)]);
+
+        let (sender, receiver) = channel();
+        msc.on_marker(move |marker| {
+            let _ = sender.send(marker.frame);
+        });
+        msc.set_looping(true);
+        msc.play();
+
+        // Every pass over the marker reports it again
+        for _ in 0..5 {
+            harness.render(Duration::from_millis(50));
+        }
+        msc.stop();
+        for _ in 0..2 {
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), 2205);
+        }
+        assert!(receiver.try_iter().all(|frame| frame == 2205));
+    }
+
+    #[test]
+    fn music_on_marker_at_end_OK() -> () {
+        let mut harness = headless::lock();
+
+        // In the last 5 milliseconds, played after the last check of the
+        // position
+        let wav = headless::wav_with_cues(&[0; 4410], &[(1, 4400)]);
+        let mut msc = Music::from_bytes(wav).expect("Cannot create Music");
+
+        let (sender, receiver) = channel();
+        msc.on_marker(move |marker| {
+            let _ = sender.send(marker.frame);
+        });
+        msc.play();
+        harness.render(Duration::from_millis(200));
+        assert_eq!(msc.get_state(), Stopped);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), 4400);
+    }
+
+    #[test]
+    fn music_seek_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc = Music::from_bytes(headless::wav(&[0; 88200])).expect("Cannot create Music");
+        msc.seek(Duration::from_millis(500));
+        assert_eq!(msc.get_offset(), 22050);
+
+        msc.play();
+        msc.seek(Duration::from_secs(1));
+        harness.render(Duration::from_millis(100));
+
+        let position = msc.position();
+        assert!(position >= Duration::from_secs(1));
+        assert!(position < Duration::from_millis(1250));
+        msc.stop();
+    }
+
+    #[test]
+    fn music_beat_position_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc = Music::from_bytes(headless::wav(&[0; 44100])).expect("Cannot create Music");
+        assert_eq!(msc.get_beat_position(), None);
+
+        // A beat every 100ms, a bar every 400ms
+        msc.set_beat_clock(BeatClock::new(600., 4, 4).unwrap());
+        msc.play();
+        let (sender, receiver) = channel();
+        let scheduled = msc
+            .on_next(Quantize::Beat, move |position| {
+                let _ = sender.send(position);
+            })
+            .expect("No beat clock");
+        assert_eq!((scheduled.bar, scheduled.beat, scheduled.tick), (0, 1, 0));
+
+        harness.render(Duration::from_millis(150));
+        let reached = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(reached, scheduled);
+        let position = msc.get_beat_position().expect("No beat clock");
+        assert_eq!((position.bar, position.beat), (0, 1));
+        msc.stop();
+    }
+
+    #[test]
+    fn music_skip_on_next_OK() -> () {
+        let mut harness = headless::lock();
+
+        let second = headless::TempWav::new(&[10000; 44100]);
+        let mut msc = Music::from_bytes(headless::wav(&[0; 44100])).expect("Cannot create Music");
+        msc.append(second.path()).expect("Cannot append");
+        assert_eq!(msc.skip_on_next(Quantize::Bar), None);
+
+        msc.set_beat_clock(BeatClock::new(600., 4, 4).unwrap());
+        msc.skip_on_next(Quantize::Bar);
+        msc.play();
+
+        // The first track plays until the end of the first bar, 0.4 second
+        let samples = harness.render(Duration::from_millis(600));
+        let first = samples
+            .iter()
+            .position(|sample| *sample != 0)
+            .expect("The second track isn't heard") as i64
+            / headless::CHANNELS as i64;
+        assert!((first - 17640).abs() <= 44);
+        assert_eq!(msc.get_current_track(), 1);
+        msc.stop();
+    }
+
+    #[test]
+    fn music_crossfade_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut from =
+            Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        let mut to = Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        from.set_looping(true);
+        to.set_looping(true);
+        to.set_volume(0.5);
+        from.play();
+
+        from.crossfade(&mut to, Duration::from_millis(50), FadeCurve::EqualPower);
+        assert!(from.is_playing());
+        assert!(to.is_playing());
+        assert!(to.get_volume() < 0.5);
+
+        fade::advance_clock(Duration::from_millis(50));
+        assert!(!headless::is_silent(&harness.render_short()));
+        assert_eq!(from.get_state(), Stopped);
+        assert_eq!(from.get_volume(), 1.);
+        assert_eq!(to.get_state(), Playing);
+        assert_eq!(to.get_volume(), 0.5);
+        assert_eq!(to.get_underrun_count(), 0);
+    }
+
+    #[test]
+    fn music_stop_with_fade_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut msc =
+            Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        msc.set_looping(true);
+        msc.play();
+        msc.pitch_to(0.5, Duration::from_millis(50), FadeCurve::Linear);
+        msc.stop_with_fade(Duration::from_millis(50));
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(msc.get_state(), Stopped);
+        assert_eq!(msc.get_volume(), 1.);
+        assert_eq!(msc.get_pitch(), 0.5);
+        assert_eq!(msc.get_underrun_count(), 0);
+    }
+
+    #[test]
+    fn music_crossfade_back_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut first =
+            Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        let mut second =
+            Music::from_bytes(headless::wav(&[10000; 4410])).expect("Cannot create Music");
+        first.set_looping(true);
+        second.set_looping(true);
+        first.play();
+
+        // Changing our mind halfway restores the initial volumes
+        first.crossfade(&mut second, Duration::from_millis(50), FadeCurve::Linear);
+        fade::advance_clock(Duration::from_millis(20));
+        harness.render(Duration::from_millis(20));
+        second.crossfade(&mut first, Duration::from_millis(50), FadeCurve::Linear);
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(first.get_state(), Playing);
+        assert_eq!(first.get_volume(), 1.);
+        assert_eq!(second.get_state(), Stopped);
+        assert_eq!(second.get_volume(), 1.);
+    }
+
+    #[test]
     fn music_set_attenuation() -> () {
-        let mut msc = Music::new("res/shot.wav").expect("Cannot create Music");
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
 
         msc.set_attenuation(0.5f32);
         println!("{}", &msc.get_attenuation());
@@ -69,6 +69,26 @@ pub mod ffi {
     pub const AL_FORMAT_71CHN16: i32 = 0x1211;
     pub const AL_FORMAT_QUAD16: i32 = 0x1205;
 
+    /// AL_EXT_FLOAT32 and AL_EXT_MCFORMATS float formats
+    pub const AL_FORMAT_MONO_FLOAT32: i32 = 0x10010;
+    pub const AL_FORMAT_STEREO_FLOAT32: i32 = 0x10011;
+    pub const AL_FORMAT_QUAD32: i32 = 0x1206;
+    pub const AL_FORMAT_51CHN32: i32 = 0x120C;
+    pub const AL_FORMAT_61CHN32: i32 = 0x120F;
+    pub const AL_FORMAT_71CHN32: i32 = 0x1212;
+
+    /// AL_EXT_MULAW and AL_EXT_IMA4 compressed formats
+    pub const AL_FORMAT_MONO_MULAW_EXT: i32 = 0x10014;
+    pub const AL_FORMAT_STEREO_MULAW_EXT: i32 = 0x10015;
+    pub const AL_FORMAT_MONO_IMA4: i32 = 0x1300;
+    pub const AL_FORMAT_STEREO_IMA4: i32 = 0x1301;
+
+    /// AL_SOFT_block_alignment buffer param
+    pub const AL_UNPACK_BLOCK_ALIGNMENT_SOFT: i32 = 0x200C;
+
+    /// AL_SOFT_loop_points buffer param
+    pub const AL_LOOP_POINTS_SOFT: i32 = 0x2015;
+
     /// Source params
     pub const AL_BUFFER: i32 = 0x1009;
     pub const AL_BUFFERS_PROCESSED: i32 = 0x1016;
@@ -121,6 +141,103 @@ pub mod ffi {
 
     /// ALC
     pub const ALC_CAPTURE_SAMPLES: i32 = 0x312;
+    pub const ALC_CONNECTED: i32 = 0x313;
+    pub const ALC_ATTRIBUTES_SIZE: i32 = 0x1002;
+    pub const ALC_ALL_ATTRIBUTES: i32 = 0x1003;
+    pub const ALC_FREQUENCY: i32 = 0x1007;
+    pub const ALC_REFRESH: i32 = 0x1008;
+    pub const ALC_SYNC: i32 = 0x1009;
+    pub const ALC_MONO_SOURCES: i32 = 0x1010;
+    pub const ALC_STEREO_SOURCES: i32 = 0x1011;
+    pub const ALC_MAX_AUXILIARY_SENDS: i32 = 0x20003;
+
+    /// ALC_SOFT_HRTF
+    pub const ALC_HRTF_SOFT: i32 = 0x1992;
+    pub const ALC_DONT_CARE_SOFT: i32 = 0x0002;
+    pub const ALC_HRTF_STATUS_SOFT: i32 = 0x1993;
+    pub const ALC_HRTF_DISABLED_SOFT: i32 = 0x0000;
+    pub const ALC_HRTF_ENABLED_SOFT: i32 = 0x0001;
+    pub const ALC_HRTF_DENIED_SOFT: i32 = 0x0002;
+    pub const ALC_HRTF_REQUIRED_SOFT: i32 = 0x0003;
+    pub const ALC_HRTF_HEADPHONES_DETECTED_SOFT: i32 = 0x0004;
+    pub const ALC_HRTF_UNSUPPORTED_FORMAT_SOFT: i32 = 0x0005;
+    pub const ALC_NUM_HRTF_SPECIFIERS_SOFT: i32 = 0x1994;
+    pub const ALC_HRTF_SPECIFIER_SOFT: i32 = 0x1995;
+    pub const ALC_HRTF_ID_SOFT: i32 = 0x1996;
+    pub type LPALCGETSTRINGISOFT =
+        unsafe extern "C" fn(device: ALCdevicePtr, param: i32, index: i32) -> *const c_char;
+    pub type LPALCRESETDEVICESOFT =
+        unsafe extern "C" fn(device: ALCdevicePtr, attribs: *const i32) -> ALCboolean;
+
+    /// ALC_SOFT_loopback
+    pub const ALC_FORMAT_CHANNELS_SOFT: i32 = 0x1990;
+    pub const ALC_FORMAT_TYPE_SOFT: i32 = 0x1991;
+    pub const ALC_BYTE_SOFT: i32 = 0x1400;
+    pub const ALC_UNSIGNED_BYTE_SOFT: i32 = 0x1401;
+    pub const ALC_SHORT_SOFT: i32 = 0x1402;
+    pub const ALC_UNSIGNED_SHORT_SOFT: i32 = 0x1403;
+    pub const ALC_INT_SOFT: i32 = 0x1404;
+    pub const ALC_UNSIGNED_INT_SOFT: i32 = 0x1405;
+    pub const ALC_FLOAT_SOFT: i32 = 0x1406;
+    pub const ALC_MONO_SOFT: i32 = 0x1500;
+    pub const ALC_STEREO_SOFT: i32 = 0x1501;
+    pub const ALC_QUAD_SOFT: i32 = 0x1503;
+    pub const ALC_5POINT1_SOFT: i32 = 0x1504;
+    pub const ALC_6POINT1_SOFT: i32 = 0x1505;
+    pub const ALC_7POINT1_SOFT: i32 = 0x1506;
+    pub type LPALCLOOPBACKOPENDEVICESOFT =
+        unsafe extern "C" fn(devicename: *const c_char) -> ALCdevicePtr;
+    pub type LPALCISRENDERFORMATSUPPORTEDSOFT = unsafe extern "C" fn(
+        device: ALCdevicePtr,
+        freq: i32,
+        channels: i32,
+        sample_type: i32,
+    ) -> ALCboolean;
+    pub type LPALCRENDERSAMPLESSOFT =
+        unsafe extern "C" fn(device: ALCdevicePtr, buffer: *mut c_void, samples: i32);
+    pub const ALC_DEFAULT_DEVICE_SPECIFIER: i32 = 0x1004;
+    pub const ALC_DEVICE_SPECIFIER: i32 = 0x1005;
+    pub const ALC_DEFAULT_ALL_DEVICES_SPECIFIER: i32 = 0x1012;
+    pub const ALC_ALL_DEVICES_SPECIFIER: i32 = 0x1013;
+
+    /// ALC_SOFT_reopen_device
+    pub type LPALCREOPENDEVICESOFT = unsafe extern "C" fn(
+        device: ALCdevicePtr,
+        devicename: *const c_char,
+        attribs: *const i32,
+    ) -> ALCboolean;
+
+    /// AL_SOFT_events
+    pub const AL_EVENT_TYPE_BUFFER_COMPLETED_SOFT: i32 = 0x19A4;
+    pub const AL_EVENT_TYPE_SOURCE_STATE_CHANGED_SOFT: i32 = 0x19A5;
+    pub const AL_EVENT_TYPE_DISCONNECTED_SOFT: i32 = 0x19A6;
+    pub type ALEVENTPROCSOFT = extern "C" fn(
+        event_type: i32,
+        object: u32,
+        param: u32,
+        length: i32,
+        message: *const c_char,
+        user_param: *mut c_void,
+    );
+    pub type LPALEVENTCONTROLSOFT =
+        unsafe extern "C" fn(count: i32, types: *const i32, enable: ALboolean);
+    pub type LPALEVENTCALLBACKSOFT =
+        unsafe extern "C" fn(callback: Option<ALEVENTPROCSOFT>, user_param: *mut c_void);
+
+    /// ALC_SOFT_device_clock
+    pub const ALC_DEVICE_CLOCK_SOFT: i32 = 0x1600;
+    pub type LPALCGETINTEGER64VSOFT =
+        unsafe extern "C" fn(device: ALCdevicePtr, param: i32, size: i32, values: *mut i64);
+
+    /// AL_SOFT_source_start_delay
+    pub type LPALSOURCEPLAYATTIMEVSOFT =
+        unsafe extern "C" fn(n: i32, sources: *const u32, start_time: i64);
+
+    /// AL_SOFT_source_latency
+    pub const AL_SAMPLE_OFFSET_LATENCY_SOFT: i32 = 0x1200;
+    pub const AL_SEC_OFFSET_LATENCY_SOFT: i32 = 0x1201;
+    pub type LPALSOURCEDSOFT = unsafe extern "C" fn(source: u32, param: i32, value: f64);
+    pub type LPALGETSOURCEDVSOFT = unsafe extern "C" fn(source: u32, param: i32, values: *mut f64);
 
     extern "C" {
         /// Context functions
@@ -132,6 +249,8 @@ pub mod ffi {
         /// Device functions
         pub fn alcOpenDevice(devicename: *mut c_char) -> ALCdevicePtr;
         pub fn alcCloseDevice(device: ALCdevicePtr) -> ALCboolean;
+        pub fn alcGetString(device: ALCdevicePtr, param: i32) -> *const c_char;
+        pub fn alcGetProcAddress(device: ALCdevicePtr, funcname: *const c_char) -> *mut c_void;
 
         /// Listener functions
         pub fn alListenerf(param: i32, value: f32) -> ();
@@ -153,7 +272,9 @@ pub mod ffi {
         pub fn alSource3i(source: u32, param: i32, value1: i32, value2: i32, value3: i32);
         pub fn alSourcef(source: u32, param: i32, value: f32) -> ();
         pub fn alSourcePlay(source: u32) -> ();
+        pub fn alSourcePlayv(n: i32, sources: *const u32) -> ();
         pub fn alSourcePause(source: u32) -> ();
+        pub fn alSourcePausev(n: i32, sources: *const u32) -> ();
         pub fn alSourceStop(source: u32) -> ();
         pub fn alGetSourcei(source: u32, param: i32, value: *mut i32) -> ();
         pub fn alGetSourcef(source: u32, param: i32, value: *mut f32) -> ();
@@ -187,6 +308,7 @@ pub mod ffi {
 
         /// extension check
         pub fn alIsExtensionPresent(extension: *const c_char) -> ALboolean;
+        pub fn alGetProcAddress(fname: *const c_char) -> *mut c_void;
         pub fn alcIsExtensionPresent(device: ALCdevicePtr, extension: *const c_char) -> ALCboolean;
 
         /// Buffers functions
@@ -199,6 +321,9 @@ pub mod ffi {
             size: i32,
             freq: i32,
         ) -> ();
+        pub fn alBufferi(buffer: u32, param: i32, value: i32) -> ();
+        pub fn alBufferiv(buffer: u32, param: i32, values: *const i32) -> ();
+        pub fn alGetBufferiv(buffer: u32, param: i32, values: *mut i32) -> ();
 
         /// Error
         pub fn alGetError() -> i32;
@@ -213,9 +338,11 @@ pub mod ffi {
 pub mod al {
 
     use super::ffi;
-    use libc::c_void;
+    use libc::{c_char, c_void};
     use std::error::Error;
+    use std::ffi::{CStr, CString};
     use std::fmt;
+    use std::mem;
 
     pub fn alBufferData(buffer: u32, format: i32, data: *mut c_void, size: i32, freq: i32) -> () {
         unsafe {
@@ -223,6 +350,24 @@ pub mod al {
         }
     }
 
+    pub fn alBufferi(buffer: u32, param: i32, value: i32) -> () {
+        unsafe {
+            ffi::alBufferi(buffer, param, value);
+        }
+    }
+
+    pub fn alBufferiv(buffer: u32, param: i32, values: &[i32]) -> () {
+        unsafe {
+            ffi::alBufferiv(buffer, param, values.as_ptr());
+        }
+    }
+
+    pub fn alGetBufferiv(buffer: u32, param: i32, values: &mut [i32]) -> () {
+        unsafe {
+            ffi::alGetBufferiv(buffer, param, values.as_mut_ptr());
+        }
+    }
+
     pub fn alSourceQueueBuffers(source: u32, nb: i32, buffers: *const u32) -> () {
         unsafe {
             ffi::alSourceQueueBuffers(source, nb, buffers);
@@ -235,6 +380,18 @@ pub mod al {
         }
     }
 
+    pub fn alSourcePlayv(sources: &[u32]) -> () {
+        unsafe {
+            ffi::alSourcePlayv(sources.len() as i32, sources.as_ptr());
+        }
+    }
+
+    pub fn alSourcePausev(sources: &[u32]) -> () {
+        unsafe {
+            ffi::alSourcePausev(sources.len() as i32, sources.as_ptr());
+        }
+    }
+
     pub fn alGetSourcei(source: u32, param: i32, value: *mut i32) -> () {
         unsafe {
             ffi::alGetSourcei(source, param, value);
@@ -382,6 +539,104 @@ pub mod al {
         }
     }
 
+    /// Check if an AL extension is present on the current context.
+    pub fn alIsExtensionPresent(extension: &str) -> bool {
+        let c_str = CString::new(extension).unwrap();
+        unsafe { ffi::alIsExtensionPresent(c_str.as_ptr()) == ffi::AL_TRUE }
+    }
+
+    /// Check if an ALC extension is present on the device (or globally when
+    /// the device is 0).
+    pub fn alcIsExtensionPresent(device: ffi::ALCdevicePtr, extension: &str) -> bool {
+        let c_str = CString::new(extension).unwrap();
+        unsafe { ffi::alcIsExtensionPresent(device, c_str.as_ptr()) == ffi::ALC_TRUE }
+    }
+
+    /// Load an AL extension function, None if the implementation doesn't
+    /// provide it.
+    pub fn alGetProcAddress(name: &str) -> Option<*mut c_void> {
+        let c_str = CString::new(name).unwrap();
+        let ptr = unsafe { ffi::alGetProcAddress(c_str.as_ptr()) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ptr)
+        }
+    }
+
+    /// Set a double source parameter, false without AL_SOFT_source_latency.
+    pub fn alSourcedSOFT(source: u32, param: i32, value: f64) -> bool {
+        if !alIsExtensionPresent("AL_SOFT_source_latency") {
+            return false;
+        }
+        match alGetProcAddress("alSourcedSOFT") {
+            Some(ptr) => unsafe {
+                let source_d: ffi::LPALSOURCEDSOFT = mem::transmute(ptr);
+                source_d(source, param, value);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Get double source parameters, false without AL_SOFT_source_latency.
+    pub fn alGetSourcedvSOFT(source: u32, param: i32, values: &mut [f64]) -> bool {
+        if !alIsExtensionPresent("AL_SOFT_source_latency") {
+            return false;
+        }
+        match alGetProcAddress("alGetSourcedvSOFT") {
+            Some(ptr) => unsafe {
+                let get_source_dv: ffi::LPALGETSOURCEDVSOFT = mem::transmute(ptr);
+                get_source_dv(source, param, values.as_mut_ptr());
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Load an ALC extension function, None if the implementation doesn't
+    /// provide it.
+    pub fn alcGetProcAddress(device: ffi::ALCdevicePtr, name: &str) -> Option<*mut c_void> {
+        let c_str = CString::new(name).unwrap();
+        let ptr = unsafe { ffi::alcGetProcAddress(device, c_str.as_ptr()) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ptr)
+        }
+    }
+
+    /// Query a single ALC string, None if the implementation returned NULL.
+    pub fn alcGetString(device: ffi::ALCdevicePtr, param: i32) -> Option<String> {
+        let ptr = unsafe { ffi::alcGetString(device, param) };
+        if ptr.is_null() {
+            None
+        } else {
+            let c_str = unsafe { CStr::from_ptr(ptr) };
+            Some(c_str.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Query an ALC string list.
+    ///
+    /// Device enumeration returns every name separated by a NULL character,
+    /// the end of the list being marked by two consecutive NULL characters.
+    pub fn alcGetStringList(device: ffi::ALCdevicePtr, param: i32) -> Vec<String> {
+        let mut list = Vec::new();
+        let mut ptr = unsafe { ffi::alcGetString(device, param) };
+        if ptr.is_null() {
+            return list;
+        }
+        unsafe {
+            while *ptr != 0 {
+                let c_str = CStr::from_ptr(ptr);
+                list.push(c_str.to_string_lossy().into_owned());
+                ptr = ptr.offset(c_str.to_bytes().len() as isize + 1);
+            }
+        }
+        list
+    }
+
     /// Any error that can happen during an OpenAL call.
     pub struct AlError(i32);
 
@@ -435,4 +690,26 @@ pub mod al {
             _ => return None,
         }
     }
+
+    /// Get the float format for a channel count, None if the extension
+    /// providing it is missing.
+    pub fn get_channels_format_f32(channels: i32) -> Option<i32> {
+        let (format, extension) = match channels {
+            1 => (ffi::AL_FORMAT_MONO_FLOAT32, "AL_EXT_FLOAT32"),
+            2 => (ffi::AL_FORMAT_STEREO_FLOAT32, "AL_EXT_FLOAT32"),
+            4 => (ffi::AL_FORMAT_QUAD32, "AL_EXT_MCFORMATS"),
+            5 => (ffi::AL_FORMAT_51CHN32, "AL_EXT_MCFORMATS"),
+            6 => (ffi::AL_FORMAT_61CHN32, "AL_EXT_MCFORMATS"),
+            7 => (ffi::AL_FORMAT_71CHN32, "AL_EXT_MCFORMATS"),
+            _ => return None,
+        };
+        if channels > 2 && !alIsExtensionPresent("AL_EXT_FLOAT32") {
+            return None;
+        }
+        if alIsExtensionPresent(extension) {
+            Some(format)
+        } else {
+            None
+        }
+    }
 }
@@ -0,0 +1,245 @@
+//! Start several Sounds and Musics on the same sample.
+
+use libc::c_void;
+use std::mem;
+use std::time::Duration;
+
+use device;
+use error::SoundError;
+use internal::OpenAlData;
+use music;
+use music::Music;
+use openal::{al, ffi};
+use sound;
+use sound::Sound;
+use streaming;
+use streaming::Stream;
+
+enum Member<'a> {
+    Sound(&'a mut Sound),
+    Music(&'a mut Music),
+}
+
+/**
+ * A set of Sounds and Musics started together.
+ *
+ * Playing the sources one after the other lets the mixer run between two
+ * calls, and the layers of a piece drift apart. A PlayGroup starts them all
+ * with a single OpenAL call so they begin on the same sample, right away or
+ * at a time of the device clock.
+ *
+ * Each member starts the way its own `play` would: paused ones resume, the
+ * others start from their beginning.
+ *
+ * # Example
+ * ```no_run
+ * use ears::{Music, PlayGroup, SoundError};
+ *
+ * fn main() -> Result<(), SoundError> {
+ *     let mut drums = Music::new("path/to/drums.ogg")?;
+ *     let mut bass = Music::new("path/to/bass.ogg")?;
+ *
+ *     PlayGroup::new().music(&mut drums).music(&mut bass).play();
+ *     Ok(())
+ * }
+ * ```
+ */
+#[derive(Default)]
+pub struct PlayGroup<'a> {
+    members: Vec<Member<'a>>,
+}
+
+impl<'a> PlayGroup<'a> {
+    /// Create an empty group.
+    pub fn new() -> PlayGroup<'a> {
+        Default::default()
+    }
+
+    /// Add a Sound to the group.
+    pub fn sound(mut self, sound: &'a mut Sound) -> PlayGroup<'a> {
+        self.members.push(Member::Sound(sound));
+        self
+    }
+
+    /// Add a Music to the group.
+    pub fn music(mut self, music: &'a mut Music) -> PlayGroup<'a> {
+        self.members.push(Member::Music(music));
+        self
+    }
+
+    /**
+     * Start every member of the group right away.
+     */
+    pub fn play(self) -> () {
+        check_openal_context!(());
+
+        let (sources, streams) = self.prepare();
+        al::alSourcePlayv(&sources);
+        for source in &sources {
+            streaming::started(*source);
+        }
+        for stream in streams {
+            streaming::add(stream);
+        }
+    }
+
+    /**
+     * Start every member of the group at a time of the device clock.
+     *
+     * Relies on the AL_SOFT_source_start_delay extension. The members are
+     * reported as playing until then, silently. Like `play`, the members
+     * already playing are stopped and start again from their beginning.
+     *
+     * # Argument
+     * * `time` - The time of the device clock, as returned by
+     * `ears::device::get_clock`, to start at
+     *
+     * # Return
+     * `Ok(())` on success, `Err(SoundError::MissingExtension)` if the
+     * extension isn't available, in which case nothing is started or
+     * stopped. `Err(SoundError::InternalOpenALError)` if OpenAL refused to
+     * start the sources, in which case nothing is started but the Musics
+     * that were playing are stopped.
+     */
+    pub fn play_at(self, time: Duration) -> Result<(), SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let missing = || SoundError::MissingExtension("AL_SOFT_source_start_delay".to_string());
+        if !al::alIsExtensionPresent("AL_SOFT_source_start_delay") {
+            return Err(missing());
+        }
+        let play_at_time: ffi::LPALSOURCEPLAYATTIMEVSOFT =
+            match al::alGetProcAddress("alSourcePlayAtTimevSOFT") {
+                Some(ptr) => unsafe {
+                    mem::transmute::<*mut c_void, ffi::LPALSOURCEPLAYATTIMEVSOFT>(ptr)
+                },
+                None => return Err(missing()),
+            };
+
+        let (sources, streams) = self.prepare();
+        let nanoseconds = time.as_secs() as i64 * 1_000_000_000 + time.subsec_nanos() as i64;
+        unsafe {
+            play_at_time(sources.len() as i32, sources.as_ptr(), nanoseconds);
+        }
+        // The streams of sources that didn't start are dropped unserviced
+        if let Some(err) = al::openal_has_error() {
+            return Err(SoundError::InternalOpenALError(err));
+        }
+
+        for source in &sources {
+            streaming::started(*source);
+        }
+        for stream in streams {
+            streaming::add(stream);
+        }
+        Ok(())
+    }
+
+    /**
+     * Start every member of the group after a delay.
+     *
+     * The delay is counted on the device clock, so the start is sample
+     * accurate whatever the pace of the application. Relies on the
+     * ALC_SOFT_device_clock and AL_SOFT_source_start_delay extensions.
+     *
+     * # Argument
+     * * `delay` - The time to wait before starting
+     *
+     * # Return
+     * `Ok(())` on success, `Err(SoundError::MissingExtension)` if an
+     * extension isn't available, in which case nothing is started.
+     */
+    pub fn play_in(self, delay: Duration) -> Result<(), SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        match device::get_clock() {
+            Some(clock) => self.play_at(clock + delay),
+            None => Err(SoundError::MissingExtension(
+                "ALC_SOFT_device_clock".to_string(),
+            )),
+        }
+    }
+
+    // Get the members ready, returning their sources and the streams to
+    // service once they play.
+    fn prepare(self) -> (Vec<u32>, Vec<Box<dyn Stream>>) {
+        let mut sources = Vec::with_capacity(self.members.len());
+        let mut streams = Vec::new();
+        for member in self.members {
+            match member {
+                Member::Sound(sound) => {
+                    let (source, stream) = sound::prepare_play(sound);
+                    sources.push(source);
+                    streams.extend(stream);
+                }
+                Member::Music(music) => {
+                    let (source, stream) = music::prepare_play(music);
+                    sources.push(source);
+                    streams.extend(stream);
+                }
+            }
+        }
+        (sources, streams)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use audio_controller::AudioController;
+    use error::SoundError;
+    use headless;
+    use music::Music;
+    use play_group::PlayGroup;
+    use sound::Sound;
+    use states::State::{Paused, Playing};
+    use std::time::Duration;
+
+    #[test]
+    fn play_group_play_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        PlayGroup::new().sound(&mut snd).music(&mut msc).play();
+
+        assert_eq!(snd.get_state(), Playing);
+        assert_eq!(msc.get_state(), Playing);
+        assert!(!headless::is_silent(&harness.render_short()));
+    }
+
+    #[test]
+    fn play_group_resume_OK() -> () {
+        let _harness = headless::lock();
+
+        let mut msc = Music::new("res/explosion.wav").expect("Cannot create Music");
+        msc.play();
+        msc.pause();
+        assert_eq!(msc.get_state(), Paused);
+
+        PlayGroup::new().music(&mut msc).play();
+        assert_eq!(msc.get_state(), Playing);
+    }
+
+    #[test]
+    fn play_group_play_in_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        match PlayGroup::new()
+            .sound(&mut snd)
+            .play_in(Duration::from_millis(50))
+        {
+            Ok(()) => {
+                assert!(headless::is_silent(
+                    &harness.render(Duration::from_millis(40))
+                ));
+                assert!(!headless::is_silent(&harness.render_short()));
+            }
+            // Older implementations can't delay the start
+            Err(SoundError::MissingExtension(_)) => {}
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
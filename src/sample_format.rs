@@ -0,0 +1,197 @@
+//! Precision of the samples stored in OpenAL buffers.
+
+use libc::c_void;
+use std::mem;
+use std::sync::Mutex;
+
+use openal::al;
+use sndfile::FormatType::{FormatDouble, FormatFloat, FormatPcm24, FormatPcm32, FormatSubMask};
+use sndfile::{SndFile, SndInfo};
+
+/**
+ * The precision used to store the samples of SoundDatas and Musics.
+ *
+ * Float samples need the AL_EXT_FLOAT32 extension (and AL_EXT_MCFORMATS for
+ * more than two channels), 16 bit samples are used when it is missing.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum SampleFormat {
+    /// 32 bit float samples for the files with more than 16 bits of
+    /// precision (24 bit, 32 bit and float files), 16 bit samples otherwise.
+    #[default]
+    Auto,
+    /// Always 16 bit samples.
+    Int16,
+    /// Always 32 bit float samples.
+    Float32,
+}
+
+lazy_static! {
+    static ref SAMPLE_FORMAT: Mutex<SampleFormat> = Mutex::new(SampleFormat::Auto);
+}
+
+/**
+ * Set the precision used for the SoundDatas and Musics created from now on.
+ *
+ * # Argument
+ * * `format` - The new sample format policy, `SampleFormat::Auto` by default
+ *
+ * # Example
+ * ```no_run
+ * use ears::{Music, SampleFormat, SoundError};
+ *
+ * fn main() -> Result<(), SoundError> {
+ *   ears::set_sample_format(SampleFormat::Float32);
+ *   let msc = Music::new("path/to/my/music.flac")?;
+ *   Ok(())
+ * }
+ * ```
+ */
+pub fn set_sample_format(format: SampleFormat) -> () {
+    match SAMPLE_FORMAT.lock() {
+        Ok(mut guard) => *guard = format,
+        Err(poisoned) => *poisoned.into_inner() = format,
+    }
+}
+
+/**
+ * Get the precision used for the new SoundDatas and Musics.
+ *
+ * # Return
+ * The current sample format policy.
+ */
+pub fn get_sample_format() -> SampleFormat {
+    match SAMPLE_FORMAT.lock() {
+        Ok(guard) => *guard,
+        Err(poisoned) => *poisoned.into_inner(),
+    }
+}
+
+/// Get the OpenAL float format to use for the samples described by infos,
+/// None if they must be stored as 16 bit samples.
+pub fn get_float_format(infos: &SndInfo) -> Option<i32> {
+    let float = match get_sample_format() {
+        SampleFormat::Auto => is_high_precision(infos.format),
+        SampleFormat::Int16 => false,
+        SampleFormat::Float32 => true,
+    };
+
+    if float {
+        al::get_channels_format_f32(infos.channels)
+    } else {
+        None
+    }
+}
+
+/// Check if a libsndfile format holds more than 16 bits of precision.
+fn is_high_precision(format: i32) -> bool {
+    let subformat = format & FormatSubMask as i32;
+    subformat == FormatPcm24 as i32
+        || subformat == FormatPcm32 as i32
+        || subformat == FormatFloat as i32
+        || subformat == FormatDouble as i32
+}
+
+/// Interleaved samples, stored with the precision chosen for their file.
+pub enum Samples {
+    Int16(Vec<i16>),
+    Float32(Vec<f32>),
+}
+
+impl Samples {
+    /// Create an empty sample buffer able to hold capacity samples.
+    pub fn with_capacity(float: bool, capacity: usize) -> Samples {
+        if float {
+            Samples::Float32(Vec::with_capacity(capacity))
+        } else {
+            Samples::Int16(Vec::with_capacity(capacity))
+        }
+    }
+
+    /// Get the number of samples in the buffer.
+    pub fn len(&self) -> usize {
+        match *self {
+            Samples::Int16(ref samples) => samples.len(),
+            Samples::Float32(ref samples) => samples.len(),
+        }
+    }
+
+    /// Get the number of samples the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        match *self {
+            Samples::Int16(ref samples) => samples.capacity(),
+            Samples::Float32(ref samples) => samples.capacity(),
+        }
+    }
+
+    /// Remove all the samples.
+    pub fn clear(&mut self) -> () {
+        match *self {
+            Samples::Int16(ref mut samples) => samples.clear(),
+            Samples::Float32(ref mut samples) => samples.clear(),
+        }
+    }
+
+    /// Append samples read from the file until the buffer is full.
+    ///
+    /// Return the number of samples read, 0 at the end of the file.
+    pub fn read(&mut self, file: &mut SndFile) -> usize {
+        self.read_at_most(file, usize::MAX)
+    }
+
+    /// Append at most limit samples read from the file, stopping earlier
+    /// if the buffer is full.
+    ///
+    /// Return the number of samples read, 0 at the end of the file.
+    pub fn read_at_most(&mut self, file: &mut SndFile, limit: usize) -> usize {
+        match *self {
+            Samples::Int16(ref mut samples) => {
+                let start = samples.len();
+                let end = samples.capacity().min(start.saturating_add(limit));
+                samples.resize(end, 0);
+                let read = file.read_i16(&mut samples[start..], (end - start) as i64);
+                samples.truncate(start + read.max(0) as usize);
+                read.max(0) as usize
+            }
+            Samples::Float32(ref mut samples) => {
+                let start = samples.len();
+                let end = samples.capacity().min(start.saturating_add(limit));
+                samples.resize(end, 0.);
+                let read = file.read_f32(&mut samples[start..], (end - start) as i64);
+                samples.truncate(start + read.max(0) as usize);
+                read.max(0) as usize
+            }
+        }
+    }
+
+    /// Upload the samples in an OpenAL buffer.
+    pub fn upload(&self, al_buffer: u32, al_format: i32, sample_rate: i32) -> () {
+        let (data, len) = match *self {
+            Samples::Int16(ref samples) => (
+                samples.as_ptr() as *mut c_void,
+                mem::size_of::<i16>() * samples.len(),
+            ),
+            Samples::Float32(ref samples) => (
+                samples.as_ptr() as *mut c_void,
+                mem::size_of::<f32>() * samples.len(),
+            ),
+        };
+        al::alBufferData(al_buffer, al_format, data, len as i32, sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sample_format::is_high_precision;
+    use sndfile::FormatType::{
+        FormatFlac, FormatFloat, FormatPcm16, FormatPcm24, FormatVorbis, FormatWav,
+    };
+
+    #[test]
+    fn sample_format_high_precision() -> () {
+        assert!(is_high_precision(FormatWav as i32 | FormatFloat as i32));
+        assert!(is_high_precision(FormatFlac as i32 | FormatPcm24 as i32));
+        assert!(!is_high_precision(FormatWav as i32 | FormatPcm16 as i32));
+        assert!(!is_high_precision(FormatVorbis as i32));
+    }
+}
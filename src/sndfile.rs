@@ -33,14 +33,20 @@
 #![allow(dead_code)]
 
 //use std::str::from_utf8;
+use libc::{c_char, c_void};
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::fmt;
 use std::i32::*;
 use std::intrinsics::transmute;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::mem;
 use std::ops::BitOr;
 use std::ptr;
+use std::slice;
 use std::str::*;
+use std::sync::{Arc, Mutex};
 
 #[doc(hidden)]
 mod libsndfile {
@@ -65,6 +71,53 @@ pub struct SndInfo {
     pub seekable: i32,
 }
 
+/// A loop of the instrument chunk of a file.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SndLoop {
+    pub mode: i32,
+    pub start: u32,
+    pub end: u32,
+    pub count: u32,
+}
+
+/// The instrument chunk of a file (`smpl` chunk of WAV files, `INST` and
+/// `MARK` chunks of AIFF files), holding its loops.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SndInstrument {
+    pub gain: i32,
+    pub basenote: i8,
+    pub detune: i8,
+    pub velocity_lo: i8,
+    pub velocity_hi: i8,
+    pub key_lo: i8,
+    pub key_hi: i8,
+    pub loop_count: i32,
+    pub loops: [SndLoop; 16],
+}
+
+/// A cue point of a file (`cue ` chunk of WAV files).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SndCuePoint {
+    pub indx: i32,
+    pub position: u32,
+    pub fcc_chunk: i32,
+    pub chunk_start: i32,
+    pub block_start: i32,
+    pub sample_offset: u32,
+    pub name: [c_char; 256],
+}
+
+/// The cue points of a file, libsndfile reads up to 100 of them.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SndCues {
+    pub cue_count: u32,
+    pub cue_points: [SndCuePoint; 100],
+}
+
 /// Modes availables for the open function.
 ///
 /// * Read - Read only mode
@@ -260,10 +313,98 @@ impl fmt::Debug for SndFileError {
 
 impl std::error::Error for SndFileError {}
 
-/// SndFile object, used to load/store sound from a file path or an fd.
+/// Any seekable stream a SndFile can be read from.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Stream read by a SndFile opened with new_from_reader.
+type VirtualData = Box<dyn ReadSeek>;
+
+// Callbacks of the libsndfile virtual I/O, the user data is always the
+// Mutex<VirtualData> owned by the SndFile. I/O errors are reported to
+// libsndfile as failed seeks and empty reads.
+
+fn with_virtual_data<T, F: FnOnce(&mut VirtualData) -> T>(user_data: *mut c_void, f: F) -> T {
+    let data = unsafe { &*(user_data as *const Mutex<VirtualData>) };
+    let mut guard = match data.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    f(&mut guard)
+}
+
+extern "C" fn vio_get_filelen(user_data: *mut c_void) -> i64 {
+    with_virtual_data(user_data, |reader| {
+        let position = match reader.stream_position() {
+            Ok(position) => position,
+            Err(_) => return -1,
+        };
+        let len = match reader.seek(SeekFrom::End(0)) {
+            Ok(len) => len as i64,
+            Err(_) => -1,
+        };
+        match reader.seek(SeekFrom::Start(position)) {
+            Ok(_) => len,
+            Err(_) => -1,
+        }
+    })
+}
+
+extern "C" fn vio_seek(offset: i64, whence: i32, user_data: *mut c_void) -> i64 {
+    with_virtual_data(user_data, |reader| {
+        let pos = match whence {
+            ffi::SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+            ffi::SEEK_CUR => SeekFrom::Current(offset),
+            ffi::SEEK_END => SeekFrom::End(offset),
+            _ => return -1,
+        };
+        match reader.seek(pos) {
+            Ok(position) => position as i64,
+            Err(_) => -1,
+        }
+    })
+}
+
+extern "C" fn vio_read(ptr: *mut c_void, count: i64, user_data: *mut c_void) -> i64 {
+    if count <= 0 {
+        return 0;
+    }
+    let buf = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, count as usize) };
+    with_virtual_data(user_data, |reader| {
+        // Fill as much as possible, libsndfile treats a short read as the end of the file
+        let mut read = 0;
+        while read < buf.len() {
+            match reader.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        read as i64
+    })
+}
+
+extern "C" fn vio_write(_ptr: *const c_void, _count: i64, _user_data: *mut c_void) -> i64 {
+    // Streams are read only
+    0
+}
+
+extern "C" fn vio_tell(user_data: *mut c_void) -> i64 {
+    with_virtual_data(user_data, |reader| match reader.stream_position() {
+        Ok(position) => position as i64,
+        Err(_) => -1,
+    })
+}
+
+/// SndFile object, used to load/store sound from a file path, an fd or
+/// a stream.
 pub struct SndFile {
     handle: ffi::SNDFILEhandle, //*const ffi::SNDFILE,
     info: Box<SndInfo>,
+    /// The stream read through the virtual I/O, if any
+    virtual_data: Option<Arc<Mutex<VirtualData>>>,
 }
 
 impl Clone for SndFile {
@@ -271,6 +412,7 @@ impl Clone for SndFile {
         SndFile {
             handle: self.handle,
             info: self.info.clone(),
+            virtual_data: self.virtual_data.clone(),
         }
     }
 }
@@ -308,6 +450,7 @@ impl SndFile {
             Ok(SndFile {
                 handle: tmp_sndfile,
                 info: info,
+                virtual_data: None,
             })
         }
     }
@@ -341,6 +484,7 @@ impl SndFile {
             Ok(SndFile {
                 handle: tmp_sndfile,
                 info: info,
+                virtual_data: None,
             })
         }
     }
@@ -380,6 +524,77 @@ impl SndFile {
             Ok(SndFile {
                 handle: tmp_sndfile,
                 info: info,
+                virtual_data: None,
+            })
+        }
+    }
+
+    /**
+     * Construct SndFile object reading the music from memory.
+     *
+     * # Argument
+     * * bytes - The content of the music file, in any supported format
+     *
+     * Return Ok() containing the SndFile on success, a SndFileError representation
+     * of the error otherwise.
+     */
+    pub fn new_from_bytes<B: AsRef<[u8]> + Send + 'static>(
+        bytes: B,
+    ) -> Result<SndFile, SndFileError> {
+        SndFile::new_from_reader(Cursor::new(bytes))
+    }
+
+    /**
+     * Construct SndFile object reading the music from a stream.
+     *
+     * The stream is only read when libsndfile needs more data, it is kept
+     * by the SndFile until it is dropped.
+     *
+     * # Argument
+     * * reader - The stream containing the music file, in any supported format
+     *
+     * Return Ok() containing the SndFile on success, a SndFileError representation
+     * of the error otherwise.
+     */
+    pub fn new_from_reader<R: Read + Seek + Send + 'static>(
+        reader: R,
+    ) -> Result<SndFile, SndFileError> {
+        let mut info = Box::new(SndInfo {
+            frames: 0,
+            samplerate: 0,
+            channels: 0,
+            format: 0,
+            sections: 0,
+            seekable: 0,
+        });
+        let mut virtual_io = ffi::SF_VIRTUAL_IO {
+            get_filelen: vio_get_filelen,
+            seek: vio_seek,
+            read: vio_read,
+            write: vio_write,
+            tell: vio_tell,
+        };
+        let reader: VirtualData = Box::new(reader);
+        let virtual_data = Arc::new(Mutex::new(reader));
+        let tmp_sndfile = unsafe {
+            ffi::sf_open_virtual(
+                &mut virtual_io,
+                ffi::SFM_READ,
+                &mut *info,
+                &*virtual_data as *const Mutex<VirtualData> as *mut c_void,
+            )
+        };
+        if tmp_sndfile == 0 {
+            Err(SndFileError::new(unsafe {
+                from_utf8(CStr::from_ptr(ffi::sf_strerror(0) as *const _).to_bytes())
+                    .unwrap()
+                    .to_owned()
+            }))
+        } else {
+            Ok(SndFile {
+                handle: tmp_sndfile,
+                info: info,
+                virtual_data: Some(virtual_data),
             })
         }
     }
@@ -389,6 +604,99 @@ impl SndFile {
         *self.info.clone()
     }
 
+    /**
+     * Retrieve the instrument chunk of the music.
+     *
+     * Return Some(SndInstrument) if the file has one, None otherwise.
+     */
+    pub fn get_instrument(&self) -> Option<SndInstrument> {
+        let mut instrument: SndInstrument = unsafe { mem::zeroed() };
+        let found = unsafe {
+            ffi::sf_command(
+                self.handle,
+                ffi::SFC_GET_INSTRUMENT,
+                &mut instrument as *mut SndInstrument as *mut c_void,
+                mem::size_of::<SndInstrument>() as i32,
+            )
+        };
+        if found == ffi::SF_TRUE {
+            Some(instrument)
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Retrieve the first loop of the instrument chunk of the music.
+     *
+     * Return Some((start, end)) in frames, the end being excluded, if the
+     * file has a valid loop, None otherwise.
+     *
+     * The end of a WAV smpl loop is the last frame played, libsndfile
+     * already reports it plus one, like the end of the other formats.
+     */
+    pub fn get_loop_points(&self) -> Option<(i64, i64)> {
+        let instrument = self.get_instrument()?;
+        let count = instrument.loop_count.clamp(0, 16) as usize;
+        instrument.loops[..count]
+            .iter()
+            .filter(|snd_loop| snd_loop.mode != ffi::SF_LOOP_NONE)
+            .map(|snd_loop| {
+                (
+                    snd_loop.start as i64,
+                    (snd_loop.end as i64).min(self.info.frames),
+                )
+            })
+            .find(|&(start, end)| start < end)
+    }
+
+    /**
+     * Retrieve the cue points of the music.
+     *
+     * Return the name and the frame of each cue point, a cue point without
+     * a name being named after its identifier. Empty if the file has none.
+     */
+    pub fn get_cue_points(&self) -> Vec<(String, i64)> {
+        let mut count: u32 = 0;
+        let found = unsafe {
+            ffi::sf_command(
+                self.handle,
+                ffi::SFC_GET_CUE_COUNT,
+                &mut count as *mut u32 as *mut c_void,
+                mem::size_of::<u32>() as i32,
+            )
+        };
+        if found != ffi::SF_TRUE || count == 0 {
+            return Vec::new();
+        }
+
+        let mut cues: Box<SndCues> = Box::new(unsafe { mem::zeroed() });
+        let found = unsafe {
+            ffi::sf_command(
+                self.handle,
+                ffi::SFC_GET_CUE,
+                &mut *cues as *mut SndCues as *mut c_void,
+                mem::size_of::<SndCues>() as i32,
+            )
+        };
+        if found != ffi::SF_TRUE {
+            return Vec::new();
+        }
+
+        let count = (cues.cue_count as usize).min(cues.cue_points.len());
+        cues.cue_points[..count]
+            .iter()
+            .map(|cue| {
+                let name = unsafe { CStr::from_ptr(cue.name.as_ptr()) };
+                let name = match name.to_str() {
+                    Ok(name) if !name.is_empty() => name.to_string(),
+                    _ => cue.indx.to_string(),
+                };
+                (name, cue.sample_offset as i64)
+            })
+            .collect()
+    }
+
     /**
      * Retrieve a tag contained by the music.
      *
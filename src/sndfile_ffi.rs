@@ -57,6 +57,17 @@ pub const SEEK_SET: SEEK_MODE = 0;
 pub const SEEK_CUR: SEEK_MODE = 1;
 pub const SEEK_END: SEEK_MODE = 2;
 
+pub type SF_COMMAND = i32;
+pub const SFC_GET_CUE_COUNT: SF_COMMAND = 0x10CD;
+pub const SFC_GET_CUE: SF_COMMAND = 0x10CE;
+pub const SFC_GET_INSTRUMENT: SF_COMMAND = 0x10D0;
+
+pub type SF_LOOP = i32;
+pub const SF_LOOP_NONE: SF_LOOP = 800;
+pub const SF_LOOP_FORWARD: SF_LOOP = 801;
+pub const SF_LOOP_BACKWARD: SF_LOOP = 802;
+pub const SF_LOOP_ALTERNATING: SF_LOOP = 803;
+
 pub type FORMAT_TYPE = i32;
 pub const SF_FORMAT_WAV: FORMAT_TYPE = 0x010000;
 /// Microsoft WAV format (little endian)
@@ -174,6 +185,22 @@ pub const SF_FORMAT_ENDMASK: FORMAT_TYPE = 0x30000000;
 pub type SNDFILE = c_void;
 pub type SNDFILEhandle = intptr_t;
 
+pub type sf_vio_get_filelen = extern "C" fn(user_data: *mut c_void) -> i64;
+pub type sf_vio_seek = extern "C" fn(offset: i64, whence: i32, user_data: *mut c_void) -> i64;
+pub type sf_vio_read = extern "C" fn(ptr: *mut c_void, count: i64, user_data: *mut c_void) -> i64;
+pub type sf_vio_write =
+    extern "C" fn(ptr: *const c_void, count: i64, user_data: *mut c_void) -> i64;
+pub type sf_vio_tell = extern "C" fn(user_data: *mut c_void) -> i64;
+
+#[repr(C)]
+pub struct SF_VIRTUAL_IO {
+    pub get_filelen: sf_vio_get_filelen,
+    pub seek: sf_vio_seek,
+    pub read: sf_vio_read,
+    pub write: sf_vio_write,
+    pub tell: sf_vio_tell,
+}
+
 #[repr(C)]
 pub struct FormatInfo {
     pub format: i32,
@@ -189,10 +216,16 @@ extern "C" {
         info: *mut SndInfo,
         close_desc: SF_BOOL,
     ) -> SNDFILEhandle;
+    pub fn sf_open_virtual(
+        sfvirtual: *mut SF_VIRTUAL_IO,
+        mode: SF_MODE,
+        info: *mut SndInfo,
+        user_data: *mut c_void,
+    ) -> SNDFILEhandle;
     pub fn sf_format_check(info: *mut SndInfo) -> SF_BOOL;
 
     pub fn sf_seek(sndfile: SNDFILEhandle, frames: i64, whence: i32) -> i64;
-    pub fn sf_command(sndfile: SNDFILEhandle, cmd: i32, data: *mut c_void, datasize: i32) -> Error;
+    pub fn sf_command(sndfile: SNDFILEhandle, cmd: i32, data: *mut c_void, datasize: i32) -> i32;
 
     pub fn sf_error(sndfile: SNDFILEhandle) -> Error;
     pub fn sf_strerror(sndfile: SNDFILEhandle) -> *mut c_char;
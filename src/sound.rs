@@ -23,18 +23,28 @@
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
-use audio_controller::AudioController;
+use audio_controller::{AudioController, AudioControllerExt};
 use audio_tags::{AudioTags, Tags};
 use error::SoundError;
+use fade::{FadeCurve, Ramp};
 use internal::OpenAlData;
+use markers;
+use markers::{Marker, MarkerCallback, MarkerTracker};
 use openal::{al, ffi};
 use reverb_effect::ReverbEffect;
 use sound_data; //::*;//{SoundData};
 use sound_data::SoundData;
 use states::State;
 use states::State::{Initial, Paused, Playing, Stopped};
+use streaming;
+use streaming::{Callback, Stream};
+
+/// Time between two checks of the position of a Sound that isn't playing.
+const IDLE_INTERVAL_MS: u64 = 250;
 
 /**
  * Play Sounds easily.
@@ -56,7 +66,7 @@ use states::State::{Initial, Paused, Playing, Stopped};
  *    snd.play();
  *
  *    // Wait until the sound stopped playing
- *    while snd.is_playing() {}
+ *    snd.finished().recv().ok();
  *
  *    Ok(())
  * }
@@ -67,6 +77,9 @@ pub struct Sound {
     al_source: u32,
     /// The SoundData associated to the Sound.
     sound_data: Rc<RefCell<SoundData>>,
+    /// The markers of the Sound, shared with the streaming thread
+    markers: Arc<Mutex<SoundMarkers>>,
+    marker_callback: MarkerCallback,
 }
 
 impl Sound {
@@ -139,9 +152,12 @@ impl Sound {
             return Err(SoundError::InternalOpenALError(err));
         };
 
+        let markers = SoundMarkers::new(&*sound_data.borrow());
         Ok(Sound {
             al_source: source_id,
             sound_data: sound_data,
+            markers: Arc::new(Mutex::new(markers)),
+            marker_callback: markers::new_callback(),
         })
     }
 
@@ -197,9 +213,67 @@ impl Sound {
             sound_data::get_buffer(&*sound_data.borrow()) as i32,
         );
 
+        *lock(&self.markers) = SoundMarkers::new(&*sound_data.borrow());
         self.sound_data = sound_data
     }
 
+    /**
+     * Add a marker to the Sound.
+     *
+     * The markers are reset to the cue points of the SoundData when it is
+     * changed with set_datas.
+     *
+     * # Arguments
+     * * `name` - The name of the marker
+     * * `frame` - The frame the marker is at
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, AudioControllerExt, Sound, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *     let mut snd = Sound::new("path/to/the/sound.wav")?;
+     *     snd.add_marker("impact", 22050);
+     *     snd.on_marker(|marker| println!("reached {}", marker.name));
+     *     snd.play();
+     *     Ok(())
+     * }
+     * ```
+     */
+    pub fn add_marker(&mut self, name: &str, frame: i64) -> () {
+        markers::insert(&mut lock(&self.markers).markers, Marker::new(name, frame));
+    }
+
+    /**
+     * Get the markers of the Sound.
+     *
+     * # Return
+     * The markers sorted by frame.
+     */
+    pub fn get_markers(&self) -> Vec<Marker> {
+        lock(&self.markers).markers.clone()
+    }
+
+    /**
+     * Remove the markers of the Sound, including the cue points of its
+     * SoundData.
+     */
+    pub fn clear_markers(&mut self) -> () {
+        lock(&self.markers).markers.clear();
+    }
+
+    // Move the playback with a function and tell the stream reporting the
+    // markers, the frames skipped or played again aren't crossed. The
+    // markers are locked meanwhile so the stream never sees the new
+    // position alone.
+    fn move_playback<F: FnOnce()>(&self, move_to: F) -> () {
+        let mut markers = lock(&self.markers);
+        move_to();
+        let mut offset: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut offset);
+        markers.moved_to = Some(offset as i64);
+    }
+
     /**
     * This is a multiplier on the amount of Air Absorption applied to the Source.
     * The air absorption factor is multiplied by an internal Air Absorption Gain
@@ -294,10 +368,16 @@ impl AudioController for Sound {
     fn play(&mut self) -> () {
         check_openal_context!(());
 
-        al::alSourcePlay(self.al_source);
+        let (source, stream) = prepare_play(self);
+        al::alSourcePlay(source);
 
         match al::openal_has_error() {
-            None => {}
+            None => {
+                streaming::started(source);
+                if let Some(stream) = stream {
+                    streaming::add(stream);
+                }
+            }
             Some(err) => println!("Internal OpenAL error: {}", err),
         }
     }
@@ -343,9 +423,40 @@ impl AudioController for Sound {
     fn stop(&mut self) -> () {
         check_openal_context!(());
 
+        // The markers left are only reported when the Sound reaches its end
+        streaming::cancel_ramps(self.al_source);
+        streaming::remove(self.al_source);
         al::alSourceStop(self.al_source)
     }
 
+    /**
+     * Fade the volume of the Sound out, then stop it.
+     *
+     * The volume goes back to its value before the fade once the Sound is
+     * stopped.
+     *
+     * # Argument
+     * * `duration` - The duration of the fade
+     */
+    fn stop_with_fade(&mut self, duration: Duration) -> () {
+        check_openal_context!(());
+
+        // Fading out again keeps the volume the first fade started from
+        let volume = streaming::settled_value(self.al_source, ffi::AL_GAIN)
+            .unwrap_or_else(|| self.get_volume());
+        streaming::add_ramp(
+            Ramp::new(
+                self.al_source,
+                ffi::AL_GAIN,
+                self.get_volume(),
+                0.,
+                duration,
+                FadeCurve::Linear,
+            )
+            .then_stop(volume),
+        );
+    }
+
     /**
      * Connect a ReverbEffect to the Sound
      *
@@ -462,7 +573,8 @@ impl AudioController for Sound {
     fn set_offset(&mut self, offset: i32) -> () {
         check_openal_context!(());
 
-        al::alSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, offset);
+        let source = self.al_source;
+        self.move_playback(|| al::alSourcei(source, ffi::AL_SAMPLE_OFFSET, offset));
     }
 
     /**
@@ -492,6 +604,7 @@ impl AudioController for Sound {
     fn set_volume(&mut self, volume: f32) -> () {
         check_openal_context!(());
 
+        streaming::cancel_ramp(self.al_source, ffi::AL_GAIN);
         al::alSourcef(self.al_source, ffi::AL_GAIN, volume);
     }
 
@@ -509,6 +622,27 @@ impl AudioController for Sound {
         volume
     }
 
+    /**
+     * Change the volume of the Sound progressively.
+     *
+     * # Arguments
+     * * `volume` - The volume to reach, should be between 0.0 and 1.0
+     * * `duration` - The duration of the fade
+     * * `curve` - The shape of the fade
+     */
+    fn fade_to(&mut self, volume: f32, duration: Duration, curve: FadeCurve) -> () {
+        check_openal_context!(());
+
+        streaming::add_ramp(Ramp::new(
+            self.al_source,
+            ffi::AL_GAIN,
+            self.get_volume(),
+            volume,
+            duration,
+            curve,
+        ));
+    }
+
     /**
      * Set the minimal volume for a Sound.
      *
@@ -618,6 +752,7 @@ impl AudioController for Sound {
     fn set_pitch(&mut self, pitch: f32) -> () {
         check_openal_context!(());
 
+        streaming::cancel_ramp(self.al_source, ffi::AL_PITCH);
         al::alSourcef(self.al_source, ffi::AL_PITCH, pitch)
     }
 
@@ -635,6 +770,27 @@ impl AudioController for Sound {
         pitch
     }
 
+    /**
+     * Change the pitch of the Sound progressively.
+     *
+     * # Arguments
+     * * `pitch` - The pitch to reach in the range [0.5 - 2.0]
+     * * `duration` - The duration of the change
+     * * `curve` - The shape of the change
+     */
+    fn pitch_to(&mut self, pitch: f32, duration: Duration, curve: FadeCurve) -> () {
+        check_openal_context!(());
+
+        streaming::add_ramp(Ramp::new(
+            self.al_source,
+            ffi::AL_PITCH,
+            self.get_pitch(),
+            pitch,
+            duration,
+            curve,
+        ));
+    }
+
     /**
      * Set the position of the sound relative to the listener or absolute.
      *
@@ -922,10 +1078,263 @@ impl AudioController for Sound {
     }
 }
 
+impl AudioControllerExt for Sound {
+    /**
+     * Call a function once the Sound stops.
+     *
+     * # Argument
+     * * `callback` - The function to call, from a background thread
+     */
+    fn on_finished<F>(&mut self, callback: F) -> ()
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        check_openal_context!(());
+
+        streaming::watch(self.al_source, Box::new(callback));
+    }
+
+    /**
+     * Get a channel receiving a message once the Sound stops.
+     *
+     * # Return
+     * The Receiver end of the channel.
+     */
+    fn finished(&mut self) -> Receiver<()> {
+        let (sender, receiver) = channel();
+        self.on_finished(move || {
+            let _ = sender.send(());
+        });
+        receiver
+    }
+
+    /**
+     * Call a function each time the playback crosses a marker.
+     *
+     * # Argument
+     * * `callback` - The function to call with the crossed marker, from a
+     * background thread
+     */
+    fn on_marker<F>(&mut self, callback: F) -> ()
+    where
+        F: FnMut(&Marker) + Send + 'static,
+    {
+        check_openal_context!(());
+
+        // The position of a Sound already playing is followed from the
+        // first callback on, the next plays follow it themselves
+        let first = !markers::has_callback(&self.marker_callback);
+        markers::set_callback(&self.marker_callback, Box::new(callback));
+        if first {
+            match al::alGetState(self.al_source) {
+                ffi::AL_PLAYING | ffi::AL_PAUSED => {
+                    let mut offset: i32 = 0;
+                    al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut offset);
+                    streaming::add(Box::new(MarkerStream::new(self, offset as i64)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /**
+     * Move the playback of the Sound to a time.
+     *
+     * # Argument
+     * * `position` - The time from the start of the Sound
+     */
+    fn seek(&mut self, position: Duration) -> () {
+        check_openal_context!(());
+
+        // Seconds are only as precise as a frame in double precision
+        let seconds = position.as_secs_f64();
+        let source = self.al_source;
+        self.move_playback(|| {
+            if !al::alSourcedSOFT(source, ffi::AL_SEC_OFFSET, seconds) {
+                al::alSourcef(source, ffi::AL_SEC_OFFSET, seconds as f32);
+            }
+        });
+    }
+
+    /**
+     * Get the time of the Sound being heard.
+     *
+     * # Return
+     * The time from the start of the Sound
+     */
+    fn position(&self) -> Duration {
+        check_openal_context!(Duration::from_secs(0));
+
+        let mut values = [0.; 2];
+        let seconds = if al::alGetSourcedvSOFT(
+            self.al_source,
+            ffi::AL_SEC_OFFSET_LATENCY_SOFT,
+            &mut values,
+        ) {
+            // The samples at the offset are heard once the latency elapsed
+            if al::alGetState(self.al_source) == ffi::AL_PLAYING {
+                values[0] - values[1]
+            } else {
+                values[0]
+            }
+        } else {
+            let mut offset: f32 = 0.;
+            al::alGetSourcef(self.al_source, ffi::AL_SEC_OFFSET, &mut offset);
+            offset as f64
+        };
+        Duration::from_secs_f64(seconds.max(0.))
+    }
+}
+
+/**
+ * Get a Sound ready to be played.
+ *
+ * A paused Sound is left as is to be resumed, otherwise the stream
+ * reporting its markers is replaced by one following the new play.
+ *
+ * # Return
+ * The OpenAL source to play and the stream to service once it plays, None
+ * if the Sound is resumed or has no marker callback.
+ */
+pub(crate) fn prepare_play(sound: &Sound) -> (u32, Option<Box<dyn Stream>>) {
+    let state = al::alGetState(sound.al_source);
+    if state == ffi::AL_PAUSED || !markers::has_callback(&sound.marker_callback) {
+        return (sound.al_source, None);
+    }
+
+    streaming::remove(sound.al_source);
+    lock(&sound.markers).moved_to = None;
+    // A Sound playing again restarts from its beginning, a stopped one
+    // from the offset it was moved to
+    let mut offset: i32 = 0;
+    if state != ffi::AL_PLAYING {
+        al::alGetSourcei(sound.al_source, ffi::AL_SAMPLE_OFFSET, &mut offset);
+    }
+    let stream = MarkerStream::new(sound, offset as i64);
+    (sound.al_source, Some(Box::new(stream)))
+}
+
+/// The markers of a Sound and the frames it loops on.
+struct SoundMarkers {
+    markers: Vec<Marker>,
+    /// The looped frames, the end being excluded
+    loop_region: (i64, i64),
+    /// Number of frames of the SoundData
+    frames: i64,
+    /// Frame the application moved the playback to since the last check
+    /// of the position
+    moved_to: Option<i64>,
+}
+
+impl SoundMarkers {
+    fn new(sound_data: &SoundData) -> SoundMarkers {
+        let frames = sound_data::get_sndinfo(sound_data).frames;
+        let loop_region = match sound_data.get_loop_points() {
+            Some((start, end)) => (start as i64, end as i64),
+            None => (0, frames),
+        };
+        SoundMarkers {
+            markers: sound_data.get_markers(),
+            loop_region: loop_region,
+            frames: frames,
+            moved_to: None,
+        }
+    }
+}
+
+fn lock(markers: &Mutex<SoundMarkers>) -> MutexGuard<'_, SoundMarkers> {
+    match markers.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Follows the position of a Sound to report its markers, from a play
+/// until the Sound stops.
+struct MarkerStream {
+    al_source: u32,
+    markers: Arc<Mutex<SoundMarkers>>,
+    callback: MarkerCallback,
+    tracker: MarkerTracker,
+}
+
+impl MarkerStream {
+    fn new(sound: &Sound, offset: i64) -> MarkerStream {
+        MarkerStream {
+            al_source: sound.al_source,
+            markers: sound.markers.clone(),
+            callback: sound.marker_callback.clone(),
+            tracker: MarkerTracker::new(offset),
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        al::alGetState(self.al_source) == ffi::AL_PLAYING
+    }
+}
+
+impl Stream for MarkerStream {
+    fn source(&self) -> u32 {
+        self.al_source
+    }
+
+    fn poll_interval(&self) -> Duration {
+        if self.is_playing() {
+            Duration::from_millis(markers::MARKER_INTERVAL_MS)
+        } else {
+            Duration::from_millis(IDLE_INTERVAL_MS)
+        }
+    }
+
+    fn wake_interval(&self) -> Option<Duration> {
+        if self.is_playing() {
+            Some(Duration::from_millis(markers::MARKER_INTERVAL_MS))
+        } else {
+            None
+        }
+    }
+
+    fn service(&mut self, callbacks: &mut Vec<Callback>) -> bool {
+        // Locked first, the position isn't read while the Sound moves it
+        let mut markers = lock(&self.markers);
+        let mut offset: i32 = 0;
+        al::alGetSourcei(self.al_source, ffi::AL_SAMPLE_OFFSET, &mut offset);
+
+        if let Some(frame) = markers.moved_to.take() {
+            self.tracker.jump(frame);
+        }
+        let (position, loop_region, playing) = match al::alGetState(self.al_source) {
+            ffi::AL_PLAYING | ffi::AL_PAUSED => {
+                let mut looping: i32 = 0;
+                al::alGetSourcei(self.al_source, ffi::AL_LOOPING, &mut looping);
+                let loop_region = if looping == ffi::ALC_TRUE as i32 {
+                    Some(markers.loop_region)
+                } else {
+                    None
+                };
+                (offset as i64, loop_region, true)
+            }
+            // Stopping the Sound drops the stream, it reached its end, maybe
+            // since the last service
+            _ => (markers.frames, None, false),
+        };
+        for marker in self
+            .tracker
+            .advance(&markers.markers, position, loop_region)
+        {
+            callbacks.push(markers::notify(&self.callback, marker));
+        }
+        playing
+    }
+}
+
 //#[unsafe_destructor]
 impl Drop for Sound {
     ///Destroy all the resources attached to the Sound.
     fn drop(&mut self) -> () {
+        streaming::cancel_ramps(self.al_source);
+        streaming::unwatch(self.al_source);
+        streaming::remove(self.al_source);
         unsafe {
             ffi::alDeleteSources(1, &mut self.al_source);
         }
@@ -936,62 +1345,227 @@ impl Drop for Sound {
 mod test {
     #![allow(non_snake_case)]
 
-    use audio_controller::AudioController;
+    use audio_controller::{AudioController, AudioControllerExt};
+    use fade;
+    use fade::FadeCurve;
+    use headless;
     use sound::Sound;
     use states::State::{Paused, Playing, Stopped};
+    use std::sync::mpsc::{channel, RecvError};
+    use std::thread::sleep;
+    use std::time::Duration;
 
     #[test]
-    #[ignore]
     fn sound_create_OK() -> () {
-        let snd = Sound::new("res/shot.wav");
+        let _harness = headless::lock();
+
+        let snd = Sound::new("res/explosion.wav");
 
         assert!(snd.is_ok());
     }
 
     #[test]
-    #[ignore]
     fn sound_create_FAIL() -> () {
+        let _harness = headless::lock();
+
         let snd = Sound::new("toto.wav");
 
         assert!(snd.is_err());
     }
 
     #[test]
-    #[ignore]
     fn sound_play_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.play();
         assert_eq!(snd.get_state() as i32, Playing as i32);
+        assert!(!headless::is_silent(&harness.render_short()));
         snd.stop();
     }
 
     #[test]
-    #[ignore]
     fn sound_pause_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.play();
+        harness.render_short();
         snd.pause();
+        let offset = snd.get_offset();
         assert_eq!(snd.get_state() as i32, Paused as i32);
+        assert!(headless::is_silent(&harness.render_short()));
+        assert_eq!(snd.get_offset(), offset);
+        snd.stop();
+    }
+
+    #[test]
+    fn sound_seek_OK() -> () {
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+
+        snd.play();
+        snd.pause();
+        snd.seek(Duration::from_millis(2500));
+        let position = snd.position().as_secs_f64();
+        assert!((position - 2.5).abs() < 0.001);
+        snd.stop();
+    }
+
+    #[test]
+    fn sound_on_marker_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.add_marker("late", 4410);
+        snd.add_marker("early", 0);
+        assert_eq!(snd.get_markers()[0].name, "early");
+
+        let (sender, receiver) = channel();
+        snd.on_marker(move |marker| {
+            let _ = sender.send(marker.name.clone());
+        });
+        snd.play();
+        harness.render(Duration::from_millis(200));
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "early");
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "late");
+        snd.stop();
+
+        snd.clear_markers();
+        assert!(snd.get_markers().is_empty());
+    }
+
+    #[test]
+    fn sound_on_marker_at_end_OK() -> () {
+        let mut harness = headless::lock();
+
+        let wav = headless::TempWav::new(&[0; 4410]);
+        let mut snd = Sound::new(wav.path()).expect("Cannot create sound");
+        // In the last 5 milliseconds, played after the last check of the
+        // position
+        snd.add_marker("end", 4400);
+
+        let (sender, receiver) = channel();
+        snd.on_marker(move |marker| {
+            let _ = sender.send(marker.name.clone());
+        });
+        snd.play();
+        harness.render(Duration::from_millis(200));
+        assert_eq!(snd.get_state(), Stopped);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap(),
+            "end"
+        );
+
+        // A stopped Sound didn't reach the marker
+        snd.play();
+        snd.stop();
+        harness.render(Duration::from_millis(200));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn sound_on_marker_seek_back_OK() -> () {
+        let mut harness = headless::lock();
+
+        let wav = headless::TempWav::new(&[0; 44100]);
+        let mut snd = Sound::new(wav.path()).expect("Cannot create sound");
+        snd.add_marker("early", 4410);
+        snd.add_marker("late", 39690);
+
+        let (sender, receiver) = channel();
+        snd.on_marker(move |marker| {
+            let _ = sender.send(marker.name.clone());
+        });
+        snd.set_looping(true);
+        snd.play();
+        harness.render(Duration::from_millis(500));
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "early");
+        assert!(receiver.try_recv().is_err());
+
+        // Moving back isn't a pass over the end of the loop
+        snd.set_offset(2205);
+        harness.render(Duration::from_millis(20));
+        assert!(receiver.try_recv().is_err());
+        snd.seek(Duration::from_millis(50));
+        harness.render(Duration::from_millis(20));
+        assert!(receiver.try_recv().is_err());
+
+        harness.render(Duration::from_millis(100));
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "early");
+        assert!(receiver.try_recv().is_err());
         snd.stop();
     }
 
     #[test]
-    #[ignore]
+    fn sound_finished_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+
+        // Waits for the sound to play, then reach its end
+        let finished = snd.finished();
+        harness.render_short();
+        assert!(finished.try_recv().is_err());
+
+        snd.play();
+        harness.render_short();
+        assert!(finished.try_recv().is_err());
+
+        harness.render(Duration::from_secs(7));
+        assert!(finished.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn sound_on_finished_stop_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        let (sender, receiver) = channel();
+        snd.play();
+        snd.on_finished(move || sender.send("stopped").unwrap());
+
+        snd.stop();
+        harness.render_short();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok("stopped"));
+    }
+
+    #[test]
+    fn sound_finished_drop_OK() -> () {
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.play();
+        let finished = snd.finished();
+
+        drop(snd);
+        assert_eq!(finished.recv(), Err(RecvError));
+    }
+
+    #[test]
     fn sound_stop_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.play();
         snd.stop();
         assert_eq!(snd.get_state() as i32, Stopped as i32);
+        assert!(headless::is_silent(&harness.render_short()));
         snd.stop();
     }
 
     #[test]
-    #[ignore]
     fn sound_is_playing_TRUE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.play();
         assert_eq!(snd.is_playing(), true);
@@ -999,23 +1573,112 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn sound_is_playing_FALSE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         assert_eq!(snd.is_playing(), false);
         snd.stop();
     }
 
     #[test]
-    #[ignore]
     fn sound_set_volume_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_volume(0.7);
         assert_eq!(snd.get_volume(), 0.7);
     }
 
+    #[test]
+    fn sound_fade_to_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.set_volume(0.);
+        snd.fade_to(0.8, Duration::from_millis(50), FadeCurve::EqualPower);
+        snd.play();
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(snd.get_volume(), 0.8);
+    }
+
+    #[test]
+    fn sound_set_volume_cancels_fade_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.fade_to(0., Duration::from_millis(50), FadeCurve::Linear);
+        snd.set_volume(0.5);
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(snd.get_volume(), 0.5);
+    }
+
+    #[test]
+    fn sound_stop_with_fade_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.set_looping(true);
+        snd.set_volume(0.6);
+        snd.play();
+        snd.stop_with_fade(Duration::from_millis(50));
+        assert_eq!(snd.get_state(), Playing);
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(snd.get_state(), Stopped);
+        assert_eq!(snd.get_volume(), 0.6);
+    }
+
+    #[test]
+    fn sound_pitch_to_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        snd.pitch_to(1.5, Duration::from_millis(50), FadeCurve::Linear);
+
+        fade::advance_clock(Duration::from_millis(50));
+        harness.render_short();
+        assert_eq!(snd.get_pitch(), 1.5);
+    }
+
+    #[test]
+    fn sound_set_volume_render_OK() -> () {
+        let mut harness = headless::lock();
+
+        let mut loud = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        loud.play();
+        let loud_peak = headless::peak(&harness.render_short());
+        loud.stop();
+
+        let mut quiet = Sound::new("res/explosion.wav").expect("Cannot create sound");
+        quiet.set_volume(0.25);
+        quiet.play();
+        let quiet_peak = headless::peak(&harness.render_short());
+        quiet.stop();
+
+        assert!(quiet_peak > 0);
+        assert!(quiet_peak < loud_peak);
+    }
+
+    #[test]
+    fn sound_set_volume_zero_SILENT() -> () {
+        let mut harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
+
+        snd.set_volume(0.);
+        snd.play();
+        assert!(headless::is_silent(&harness.render_short()));
+        snd.stop();
+    }
+
     // should fail > 1.
     // #[test]
     // #[should_panic]
@@ -1027,105 +1690,116 @@ mod test {
     // }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_volume_low_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_volume(-1.);
         assert_eq!(snd.get_volume(), -1.);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_min_volume_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_min_volume(0.1);
         assert_eq!(snd.get_min_volume(), 0.1);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_min_volume_high_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_min_volume(10.9);
         assert_eq!(snd.get_min_volume(), 10.9);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_min_volume_low_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_min_volume(-1.);
         assert_eq!(snd.get_min_volume(), -1.);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_max_volume_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_max_volume(0.9);
         assert_eq!(snd.get_max_volume(), 0.9);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_max_volume_high_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_max_volume(10.9);
         assert_eq!(snd.get_max_volume(), 10.9);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_max_volume_low_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_max_volume(-1.);
         assert_eq!(snd.get_max_volume(), -1.);
     }
 
     #[test]
-    #[ignore]
     fn sound_is_looping_TRUE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_looping(true);
         assert_eq!(snd.is_looping(), true);
     }
 
     #[test]
-    #[ignore]
     fn sound_is_looping_FALSE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_looping(false);
         assert_eq!(snd.is_looping(), false);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_pitch_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_pitch(1.5);
         assert_eq!(snd.get_pitch(), 1.5);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_pitch_too_low_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_pitch(-1.);
         assert_eq!(snd.get_pitch(), -1.);
@@ -1142,18 +1816,20 @@ mod test {
     // }
 
     #[test]
-    #[ignore]
     fn sound_set_relative_TRUE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_relative(true);
         assert_eq!(snd.is_relative(), true);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_relative_FALSE() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_relative(false);
         assert_eq!(snd.is_relative(), false);
@@ -1162,9 +1838,10 @@ mod test {
     // untill https://github.com/rust-lang/rust/issues/7622 is not fixed, slice comparsion is used
 
     #[test]
-    #[ignore]
     fn sound_set_position_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_position([50f32, 150f32, 250f32]);
         let res = snd.get_position();
@@ -1172,9 +1849,10 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn sound_set_direction_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_direction([50f32, 150f32, 250f32]);
         let res = snd.get_direction();
@@ -1182,57 +1860,63 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn sound_set_max_distance_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_max_distance(70.);
         assert_eq!(snd.get_max_distance(), 70.);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_max_distance_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_max_distance(-1.);
         assert_eq!(snd.get_max_distance(), -1.);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_reference_distance_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_reference_distance(70.);
         assert_eq!(snd.get_reference_distance(), 70.);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_reference_distance_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_reference_distance(-1.);
         assert_eq!(snd.get_reference_distance(), -1.);
     }
 
     #[test]
-    #[ignore]
     fn sound_set_attenuation_OK() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_attenuation(0.5f32);
         assert_eq!(snd.get_attenuation(), 0.5f32);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sound_set_attenuation_FAIL() -> () {
-        let mut snd = Sound::new("res/shot.wav").expect("Cannot create sound");
+        let _harness = headless::lock();
+
+        let mut snd = Sound::new("res/explosion.wav").expect("Cannot create sound");
 
         snd.set_attenuation(-1.);
         assert_eq!(snd.get_attenuation(), -1.);
@@ -21,16 +21,20 @@
 
 //! The datas extracted from a sound file.
 
-use libc::c_void;
-use std::mem;
+use std::io;
 use std::vec::Vec;
 
 use audio_tags::{get_sound_tags, AudioTags, Tags};
+use compression::{encode, get_compressed_format};
 use error::SoundError;
 use internal::OpenAlData;
+use markers;
+use markers::Marker;
 use openal::{al, ffi};
+use sample_format::{get_float_format, Samples};
+use sndfile::FormatType::{FormatFloat, FormatPcm16, FormatRaw};
 use sndfile::OpenMode::Read;
-use sndfile::{SndFile, SndInfo};
+use sndfile::{FormatType, SndFile, SndInfo};
 
 /**
  * Samples extracted from a file.
@@ -71,6 +75,10 @@ pub struct SoundData {
     nb_sample: i64,
     /// The OpenAl internal identifier for the buffer
     al_buffer: u32,
+    /// The loop start and end frames, if any
+    loop_points: Option<(i32, i32)>,
+    /// The markers read from the cue points of the file
+    markers: Vec<Marker>,
 }
 
 impl SoundData {
@@ -91,25 +99,182 @@ impl SoundData {
     pub fn new(path: &str) -> Result<SoundData, SoundError> {
         check_openal_context!(Err(SoundError::InvalidOpenALContext));
 
-        let mut file = match SndFile::new(path, Read) {
-            Ok(file) => file,
-            Err(err) => {
-                return Err(SoundError::LoadError(err));
-            }
+        match SndFile::new(path, Read) {
+            Ok(file) => SoundData::from_file(file),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /**
+     * Create a new SoundData from the content of a sound file in memory.
+     *
+     * Any format supported by `new` can be decoded, the bytes can be owned
+     * or static, like the ones from `include_bytes!`.
+     *
+     * # Argument
+     * * `bytes` - The content of the sound file
+     *
+     * # Return
+     * A `Result` containing Ok(SoundData) on success, Err(SoundError)
+     * if there has been an error.
+     *
+     * # Example
+     * ```ignore
+     * use ears::{SoundData, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   let snd_data = SoundData::from_bytes(include_bytes!("path/to/my/sound.wav"))?;
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn from_bytes<B: AsRef<[u8]> + Send + 'static>(bytes: B) -> Result<SoundData, SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        match SndFile::new_from_bytes(bytes) {
+            Ok(file) => SoundData::from_file(file),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /**
+     * Create a new SoundData from a sound file read from a stream.
+     *
+     * The whole stream is decoded before returning, it can be a file in an
+     * archive, a custom virtual file system or anything else seekable.
+     *
+     * # Argument
+     * * `reader` - The stream containing the sound file
+     *
+     * # Return
+     * A `Result` containing Ok(SoundData) on success, Err(SoundError)
+     * if there has been an error.
+     */
+    pub fn from_reader<R: io::Read + io::Seek + Send + 'static>(
+        reader: R,
+    ) -> Result<SoundData, SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        match SndFile::new_from_reader(reader) {
+            Ok(file) => SoundData::from_file(file),
+            Err(err) => Err(SoundError::LoadError(err)),
+        }
+    }
+
+    /**
+     * Create a new SoundData from 16 bit samples.
+     *
+     * The samples are interleaved, one for each channel of a frame, the
+     * SoundData has no tags.
+     *
+     * # Arguments
+     * * `samples` - The interleaved samples
+     * * `channels` - The number of channels of each frame
+     * * `sample_rate` - The frequency of the samples, in Hz
+     *
+     * # Return
+     * A `Result` containing Ok(SoundData) on success, Err(SoundError)
+     * if there has been an error.
+     *
+     * # Example
+     * ```no_run
+     * use ears::{SoundData, SoundError};
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   // A 440Hz beep of half a second
+     *   let samples: Vec<i16> = (0..22050)
+     *     .map(|i| ((i as f32 * 440. * 2. * std::f32::consts::PI / 44100.).sin() * 8000.) as i16)
+     *     .collect();
+     *   let snd_data = SoundData::from_samples_i16(&samples, 1, 44100)?;
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn from_samples_i16(
+        samples: &[i16],
+        channels: i32,
+        sample_rate: i32,
+    ) -> Result<SoundData, SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let infos = raw_sndinfo(samples.len(), channels, sample_rate, FormatPcm16)?;
+        SoundData::from_samples(Samples::Int16(samples.to_vec()), infos, Tags::default())
+    }
+
+    /**
+     * Create a new SoundData from 32 bit float samples.
+     *
+     * The samples are interleaved, one for each channel of a frame, and
+     * range from -1.0 to 1.0. They are converted to 16 bit samples unless
+     * the sample format policy keeps them as float, the SoundData has no
+     * tags.
+     *
+     * # Arguments
+     * * `samples` - The interleaved samples
+     * * `channels` - The number of channels of each frame
+     * * `sample_rate` - The frequency of the samples, in Hz
+     *
+     * # Return
+     * A `Result` containing Ok(SoundData) on success, Err(SoundError)
+     * if there has been an error.
+     */
+    pub fn from_samples_f32(
+        samples: &[f32],
+        channels: i32,
+        sample_rate: i32,
+    ) -> Result<SoundData, SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        let infos = raw_sndinfo(samples.len(), channels, sample_rate, FormatFloat)?;
+        let samples = match use_float_samples(&infos) {
+            true => Samples::Float32(samples.to_vec()),
+            false => Samples::Int16(samples.iter().map(|sample| f32_to_i16(*sample)).collect()),
         };
+        SoundData::from_samples(samples, infos, Tags::default())
+    }
 
+    /// Load all the samples of an opened file in a buffer.
+    fn from_file(mut file: SndFile) -> Result<SoundData, SoundError> {
         let infos = file.get_sndinfo();
 
         let nb_sample = infos.channels as i64 * infos.frames;
 
-        let mut samples = vec![0i16; nb_sample as usize];
-        file.read_i16(&mut samples[..], nb_sample as i64);
+        let float = use_float_samples(&infos);
+        let mut samples = Samples::with_capacity(float, nb_sample as usize);
+        while samples.len() < samples.capacity() && samples.read(&mut file) > 0 {}
+
+        let sound_tags = get_sound_tags(&file);
+        let loop_points = file.get_loop_points();
+        let cue_points = file.get_cue_points();
+        file.close();
+
+        let mut sound_data = SoundData::from_samples(samples, infos, sound_tags)?;
+        for (name, frame) in cue_points {
+            markers::insert(&mut sound_data.markers, Marker::new(&name, frame));
+        }
+
+        // Loops of the file are only a hint, they are ignored when they can't be used
+        if let Some((start, end)) = loop_points {
+            if end <= i32::MAX as i64 {
+                let _ = sound_data.set_loop_points(start as i32, end as i32);
+            }
+        }
+
+        Ok(sound_data)
+    }
+
+    /// Upload interleaved samples described by infos in a new buffer.
+    fn from_samples(samples: Samples, infos: SndInfo, tags: Tags) -> Result<SoundData, SoundError> {
+        let nb_sample = infos.channels as i64 * infos.frames;
 
         let mut buffer_id = 0;
-        let len = mem::size_of::<i16>() * (samples.len());
 
         // Retrieve format informations
-        let format = match al::get_channels_format(infos.channels) {
+        let format = match samples {
+            Samples::Int16(_) => al::get_channels_format(infos.channels),
+            Samples::Float32(_) => al::get_channels_format_f32(infos.channels),
+        };
+        let format = match format {
             Some(fmt) => fmt,
             None => {
                 return Err(SoundError::InvalidFormat);
@@ -117,28 +282,137 @@ impl SoundData {
         };
 
         al::alGenBuffers(1, &mut buffer_id);
-        al::alBufferData(
-            buffer_id,
-            format,
-            samples.as_ptr() as *mut c_void,
-            len as i32,
-            infos.samplerate,
-        );
+        let encoded = match samples {
+            Samples::Int16(ref samples) => encode(samples, infos.channels),
+            Samples::Float32(_) => None,
+        };
+        match encoded {
+            Some(encoded) => encoded.upload(buffer_id, infos.samplerate),
+            None => samples.upload(buffer_id, format, infos.samplerate),
+        }
 
         if let Some(err) = al::openal_has_error() {
             return Err(SoundError::InternalOpenALError(err));
         };
 
-        let sound_data = SoundData {
-            sound_tags: get_sound_tags(&file),
+        Ok(SoundData {
+            sound_tags: tags,
             snd_info: infos,
             nb_sample: nb_sample,
             al_buffer: buffer_id,
+            loop_points: None,
+            markers: Vec::new(),
+        })
+    }
+
+    /**
+     * Set the loop points of the SoundData.
+     *
+     * A looping Sound plays once from the beginning until the end point,
+     * then loops between the start and end points instead of the whole
+     * samples. Loop points are read from the instrument chunk of the file
+     * when it has one.
+     *
+     * They can't be changed while a Sound uses the SoundData, and need the
+     * AL_SOFT_loop_points extension.
+     *
+     * # Arguments
+     * * `start` - The first frame of the loop
+     * * `end` - The frame ending the loop, excluded
+     *
+     * # Return
+     * `Ok(())` on success, Err(SoundError) if the points are out of the
+     * samples, a Sound uses the SoundData or the extension is missing.
+     *
+     * # Example
+     * ```no_run
+     * use ears::{AudioController, Sound, SoundData, SoundError};
+     * use std::cell::RefCell;
+     * use std::rc::Rc;
+     *
+     * fn main() -> Result<(), SoundError> {
+     *   let mut snd_data = SoundData::new("path/to/my/ambience.wav")?;
+     *   // Play the intro once, then loop between the frames 44100 and 441000
+     *   snd_data.set_loop_points(44100, 441000)?;
+     *
+     *   let mut snd = Sound::new_with_data(Rc::new(RefCell::new(snd_data)))?;
+     *   snd.set_looping(true);
+     *   snd.play();
+     *   Ok(())
+     * }
+     * ```
+     */
+    pub fn set_loop_points(&mut self, start: i32, end: i32) -> Result<(), SoundError> {
+        check_openal_context!(Err(SoundError::InvalidOpenALContext));
+
+        if !al::alIsExtensionPresent("AL_SOFT_loop_points") {
+            return Err(SoundError::MissingExtension(
+                "AL_SOFT_loop_points".to_string(),
+            ));
+        }
+
+        al::alBufferiv(self.al_buffer, ffi::AL_LOOP_POINTS_SOFT, &[start, end]);
+        if let Some(err) = al::openal_has_error() {
+            return Err(SoundError::InternalOpenALError(err));
         };
-        file.close();
 
-        Ok(sound_data)
+        self.loop_points = Some((start, end));
+        Ok(())
+    }
+
+    /**
+     * Get the loop points of the SoundData.
+     *
+     * # Return
+     * Some((start, end)) in frames, the end being excluded, or None if the
+     * whole samples are looped.
+     */
+    pub fn get_loop_points(&self) -> Option<(i32, i32)> {
+        self.loop_points
     }
+
+    /**
+     * Get the markers of the SoundData, read from the cue points of the
+     * file.
+     *
+     * # Return
+     * The markers sorted by frame.
+     */
+    pub fn get_markers(&self) -> Vec<Marker> {
+        self.markers.clone()
+    }
+}
+
+/// Check if samples must be kept as float, compressed samples are encoded
+/// from 16 bit samples.
+fn use_float_samples(infos: &SndInfo) -> bool {
+    get_compressed_format(infos.channels).is_none() && get_float_format(infos).is_some()
+}
+
+/// Describe raw interleaved samples like the infos of a file.
+fn raw_sndinfo(
+    len: usize,
+    channels: i32,
+    sample_rate: i32,
+    subformat: FormatType,
+) -> Result<SndInfo, SoundError> {
+    if channels <= 0 || sample_rate <= 0 || len % channels as usize != 0 {
+        return Err(SoundError::InvalidFormat);
+    }
+
+    Ok(SndInfo {
+        frames: (len / channels as usize) as i64,
+        samplerate: sample_rate,
+        channels: channels,
+        format: FormatRaw as i32 | subformat as i32,
+        sections: 1,
+        seekable: 1,
+    })
+}
+
+/// Convert a float sample to 16 bit, clipping it to [-1.0, 1.0].
+fn f32_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1., 1.) * i16::MAX as f32) as i16
 }
 
 /**
@@ -188,20 +462,193 @@ mod test {
     #![allow(non_snake_case)]
 
     #[allow(unused_variables)]
-    use sound_data::SoundData;
+    use audio_controller::AudioController;
+    use compression::{set_compression, Compression};
+    use headless;
+    use markers::Marker;
+    use sample_format::{set_sample_format, SampleFormat};
+    use sound::Sound;
+    use sound_data::{f32_to_i16, get_sndinfo, SoundData};
+    use std::cell::RefCell;
+    use std::fs::File;
+    use std::rc::Rc;
+    use std::time::Duration;
 
     #[test]
-    #[ignore]
     fn sounddata_create_OK() -> () {
-        #![allow(unused_variables)]
-        let snd_data = SoundData::new("res/shot.wav").unwrap();
+        let _harness = headless::lock();
+
+        let snd_data = SoundData::new("res/explosion.wav").unwrap();
+        let infos = get_sndinfo(&snd_data);
+
+        assert_eq!(infos.channels, 2);
+        assert_eq!(infos.samplerate, 48000);
+        assert_eq!(infos.frames, 291429);
+    }
+
+    #[test]
+    fn sounddata_from_bytes_OK() -> () {
+        let _harness = headless::lock();
+
+        let snd_data = SoundData::from_bytes(include_bytes!("../res/explosion.wav")).unwrap();
+        let infos = get_sndinfo(&snd_data);
+
+        assert_eq!(infos.channels, 2);
+        assert_eq!(infos.frames, 291429);
+    }
+
+    #[test]
+    fn sounddata_from_bytes_FAIL() -> () {
+        let _harness = headless::lock();
+
+        assert!(SoundData::from_bytes(vec![0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn sounddata_from_reader_OK() -> () {
+        let _harness = headless::lock();
+
+        let file = File::open("res/shots.ogg").expect("Cannot open file");
+        let snd_data = SoundData::from_reader(file).unwrap();
+
+        assert!(get_sndinfo(&snd_data).frames > 0);
+    }
+
+    #[test]
+    fn sounddata_from_samples_i16_OK() -> () {
+        let mut harness = headless::lock();
+
+        let samples: Vec<i16> = (0..44100).map(|i| (i % 100 * 100) as i16).collect();
+        let snd_data = SoundData::from_samples_i16(&samples, 2, 44100).unwrap();
+        let mut snd = Sound::new_with_data(Rc::new(RefCell::new(snd_data))).unwrap();
+
+        assert_eq!(snd.get_duration(), Duration::from_millis(500));
+        snd.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        snd.stop();
+    }
+
+    #[test]
+    fn sounddata_from_samples_f32_OK() -> () {
+        let _harness = headless::lock();
+
+        let samples = [0., 0.5, -0.5, 1., -1., 2.];
+        let snd_data = SoundData::from_samples_f32(&samples, 1, 22050).unwrap();
+
+        assert_eq!(get_sndinfo(&snd_data).frames, 6);
+        assert_eq!(get_sndinfo(&snd_data).samplerate, 22050);
+    }
+
+    #[test]
+    fn sounddata_float32_OK() -> () {
+        let mut harness = headless::lock();
+
+        set_sample_format(SampleFormat::Float32);
+        let samples: Vec<f32> = (0..4410).map(|i| (i % 100) as f32 / 100.).collect();
+        let snd_data = SoundData::from_samples_f32(&samples, 1, 44100).unwrap();
+        let mut snd = Sound::new_with_data(Rc::new(RefCell::new(snd_data))).unwrap();
+
+        snd.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        snd.stop();
+    }
+
+    #[test]
+    fn sounddata_compressed_OK() -> () {
+        let mut harness = headless::lock();
+
+        for compression in &[Compression::MuLaw, Compression::Ima4] {
+            set_compression(*compression);
+            let snd_data = SoundData::new("res/explosion.wav").unwrap();
+            let mut snd = Sound::new_with_data(Rc::new(RefCell::new(snd_data))).unwrap();
+
+            snd.play();
+            assert!(!headless::is_silent(&harness.render_short()));
+            snd.stop();
+        }
+    }
+
+    #[test]
+    fn sounddata_loop_points_from_file_OK() -> () {
+        let _harness = headless::lock();
+
+        let snd_data =
+            SoundData::from_bytes(headless::wav_with_loop(&[0; 4410], 1000, 2000)).unwrap();
+        let (start, end) = snd_data.get_loop_points().expect("No loop points");
+
+        assert_eq!(start, 1000);
+        assert_eq!(end, 2000);
+    }
+
+    #[test]
+    fn sounddata_markers_from_file_OK() -> () {
+        let _harness = headless::lock();
+
+        let wav = headless::wav_with_cues(&[0; 4410], &[(2, 300), (1, 100)]);
+        let snd_data = SoundData::from_bytes(wav).unwrap();
+
+        assert_eq!(
+            snd_data.get_markers(),
+            vec![Marker::new("1", 100), Marker::new("2", 300)]
+        );
+    }
+
+    #[test]
+    fn sounddata_loop_points_OK() -> () {
+        let mut harness = headless::lock();
+
+        // A short click followed by silence, looping on the silence only
+        let samples: Vec<i16> = (0..4410).map(|i| if i < 441 { 10000 } else { 0 }).collect();
+        let mut snd_data = SoundData::from_samples_i16(&samples, 1, 44100).unwrap();
+        snd_data.set_loop_points(441, 4410).unwrap();
+        assert_eq!(snd_data.get_loop_points(), Some((441, 4410)));
+
+        let mut snd = Sound::new_with_data(Rc::new(RefCell::new(snd_data))).unwrap();
+        snd.set_looping(true);
+        snd.play();
+        assert!(!headless::is_silent(&harness.render_short()));
+        assert!(headless::is_silent(
+            &harness.render(Duration::from_millis(300))
+        ));
+        assert!(snd.is_playing());
+        snd.stop();
+    }
+
+    #[test]
+    fn sounddata_loop_points_FAIL() -> () {
+        let _harness = headless::lock();
+
+        let samples = vec![0i16; 4410];
+        let mut snd_data = SoundData::from_samples_i16(&samples, 1, 44100).unwrap();
+
+        assert!(snd_data.set_loop_points(2000, 1000).is_err());
+        assert!(snd_data.set_loop_points(0, 10000).is_err());
+        assert_eq!(snd_data.get_loop_points(), None);
+    }
+
+    #[test]
+    fn sounddata_from_samples_FAIL() -> () {
+        let _harness = headless::lock();
+
+        assert!(SoundData::from_samples_i16(&[0, 0, 0], 2, 44100).is_err());
+        assert!(SoundData::from_samples_i16(&[0, 0], 0, 44100).is_err());
+        assert!(SoundData::from_samples_f32(&[0., 0.], 1, 0).is_err());
+    }
+
+    #[test]
+    fn sounddata_f32_to_i16() -> () {
+        assert_eq!(f32_to_i16(0.), 0);
+        assert_eq!(f32_to_i16(1.), 32767);
+        assert_eq!(f32_to_i16(-1.), -32767);
+        assert_eq!(f32_to_i16(4.), 32767);
     }
 
     #[test]
-    #[ignore]
     #[should_panic]
     fn sounddata_create_FAIL() -> () {
         #![allow(unused_variables)]
+        let _harness = headless::lock();
+
         let snd_data = SoundData::new("toto.wav").unwrap();
     }
 }
@@ -0,0 +1,405 @@
+//! Shared streaming thread refilling the buffers of every Music.
+//!
+//! A single "ears-stream" thread services all the streams. When the
+//! AL_SOFT_events extension is available it sleeps until OpenAL reports a
+//! played buffer or a state change, otherwise it wakes at the pace of the
+//! most demanding stream. It doesn't wake at all while no stream is playing
+//! and no ramp or watch is pending, the paused streams waiting for the
+//! wake of their resume.
+//!
+//! The same thread applies the ramps of the fades, so they stay smooth
+//! whatever the pace of the application, reports the sources reaching
+//! their end and follows the markers of the Sounds from their play to
+//! their stop.
+
+use libc::{c_char, c_void};
+use std::mem;
+use std::ptr;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use fade;
+use fade::Ramp;
+use openal::{al, ffi};
+
+/// Longest wait between two refills when OpenAL events are enabled, in
+/// case a state change isn't reported.
+const EVENT_FALLBACK_INTERVAL_MS: u64 = 250;
+
+/// Time between two steps of a ramp.
+const RAMP_INTERVAL_MS: u64 = 10;
+
+/// Time between two checks of the watched sources without OpenAL events.
+const WATCH_INTERVAL_MS: u64 = 20;
+
+/// A function called by the streaming thread once it released its lock, so
+/// it can use ears.
+pub type Callback = Box<dyn FnOnce() + Send>;
+
+/// A source refilled by the streaming thread.
+pub trait Stream: Send {
+    /// Get the OpenAL source played by the stream, identifying it.
+    fn source(&self) -> u32;
+
+    /// Get the longest time the stream can wait between two refills
+    /// without running out of samples.
+    fn poll_interval(&self) -> Duration;
+
+    /// Get the longest time the stream can wait between two services even
+    /// when OpenAL reports the played buffers, None if it only needs them.
+    fn wake_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Refill the buffers played since the last call, pushing the functions
+    /// to call in callbacks.
+    ///
+    /// Return false once the stream is finished and can be dropped.
+    fn service(&mut self, callbacks: &mut Vec<Callback>) -> bool;
+}
+
+/// A callback waiting for a source to stop.
+struct Watch {
+    source: u32,
+    /// Whether the source played since the watch was created
+    started: bool,
+    callback: Callback,
+}
+
+/// Everything serviced by the streaming thread.
+struct Jobs {
+    streams: Vec<Box<dyn Stream>>,
+    ramps: Vec<Ramp>,
+    watches: Vec<Watch>,
+}
+
+lazy_static! {
+    static ref JOBS: Mutex<Jobs> = Mutex::new(Jobs {
+        streams: Vec::new(),
+        ramps: Vec::new(),
+        watches: Vec::new(),
+    });
+    static ref WAKER: Mutex<Option<Sender<()>>> = Mutex::new(None);
+}
+
+/**
+ * Start servicing a stream.
+ *
+ * The streaming thread is started with the first stream.
+ */
+pub fn add(stream: Box<dyn Stream>) -> () {
+    lock_jobs().streams.push(stream);
+    wake();
+}
+
+/**
+ * Stop servicing the stream playing a source.
+ *
+ * Once this returns, the stream isn't being serviced and never will be
+ * again, its source and buffers can be reused.
+ */
+pub fn remove(source: u32) -> () {
+    lock_jobs()
+        .streams
+        .retain(|stream| stream.source() != source);
+}
+
+/**
+ * Start a ramp, replacing the one already changing the same parameter of
+ * the same source.
+ *
+ * If the ramp stops its source, the stream playing it is removed once the
+ * ramp ends.
+ */
+pub fn add_ramp(ramp: Ramp) -> () {
+    {
+        let mut jobs = lock_jobs();
+        jobs.ramps
+            .retain(|other| other.source() != ramp.source() || other.param() != ramp.param());
+        jobs.ramps.push(ramp);
+    }
+    wake();
+}
+
+/**
+ * Get the value a parameter of a source keeps once its ramp ended.
+ *
+ * # Return
+ * The value, None if the parameter isn't being ramped.
+ */
+pub fn settled_value(source: u32, param: i32) -> Option<f32> {
+    lock_jobs()
+        .ramps
+        .iter()
+        .find(|ramp| ramp.source() == source && ramp.param() == param)
+        .map(|ramp| ramp.settled_value())
+}
+
+/**
+ * Cancel the ramps of a source.
+ *
+ * The parameters faded to stop the source go back to their initial
+ * value, the others are left where they are.
+ */
+pub fn cancel_ramps(source: u32) -> () {
+    lock_jobs().ramps.retain(|ramp| {
+        if ramp.source() != source {
+            return true;
+        }
+        ramp.cancel();
+        false
+    });
+}
+
+/**
+ * Cancel the ramp of a parameter of a source, like cancel_ramps.
+ */
+pub fn cancel_ramp(source: u32, param: i32) -> () {
+    lock_jobs().ramps.retain(|ramp| {
+        if ramp.source() != source || ramp.param() != param {
+            return true;
+        }
+        ramp.cancel();
+        false
+    });
+}
+
+/**
+ * Call a function once a source stops.
+ *
+ * A source not playing yet is waited for until it plays then stops. The
+ * callback is called from the streaming thread, or the thread rendering a
+ * Loopback.
+ */
+pub fn watch(source: u32, callback: Callback) -> () {
+    let started = matches!(al::alGetState(source), ffi::AL_PLAYING | ffi::AL_PAUSED);
+    lock_jobs().watches.push(Watch {
+        source: source,
+        started: started,
+        callback: callback,
+    });
+    wake();
+}
+
+/**
+ * Tell the jobs of a source it just started playing.
+ *
+ * A source can stop before the streaming thread sees it playing, the
+ * watches would wait for another play. The streaming thread is woken if
+ * the source has a job, to follow it from its first frame.
+ */
+pub fn started(source: u32) -> () {
+    let mut has_job = false;
+    {
+        let mut jobs = lock_jobs();
+        for watch in jobs.watches.iter_mut() {
+            if watch.source == source {
+                watch.started = true;
+                has_job = true;
+            }
+        }
+        has_job |= jobs.streams.iter().any(|stream| stream.source() == source);
+    }
+    if has_job {
+        wake();
+    }
+}
+
+/**
+ * Forget the watches of a source without calling them.
+ */
+pub fn unwatch(source: u32) -> () {
+    lock_jobs().watches.retain(|watch| watch.source != source);
+}
+
+/**
+ * Make the streaming thread service the streams as soon as possible.
+ */
+pub fn wake() -> () {
+    let mut waker = match WAKER.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if let Some(ref sender) = *waker {
+        if sender.send(()).is_ok() {
+            return;
+        }
+    }
+
+    let (sender, receiver) = channel();
+    start_thread(receiver);
+    let _ = sender.send(());
+    *waker = Some(sender);
+}
+
+/**
+ * Service the streams from the calling thread.
+ *
+ * Used when the audio scene is rendered faster than real time, the
+ * streaming thread would be too late to refill the buffers.
+ */
+pub fn pump() -> () {
+    service_streams(false);
+}
+
+fn lock_jobs() -> MutexGuard<'static, Jobs> {
+    match JOBS.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// Step every ramp, service every stream and call the watches of the
+// stopped sources, dropping the finished ones.
+//
+// Returns how long the thread may wait before servicing them again, None
+// if there is nothing left.
+fn service_streams(events: bool) -> Option<Duration> {
+    let mut jobs = lock_jobs();
+    let Jobs {
+        ref mut streams,
+        ref mut ramps,
+        ref mut watches,
+    } = *jobs;
+
+    let now = fade::now();
+    let mut stopped = Vec::new();
+    ramps.retain(|ramp| {
+        let running = ramp.apply(now);
+        if !running && ramp.stops() {
+            stopped.push(ramp.source());
+        }
+        running
+    });
+    let mut callbacks = Vec::new();
+    streams
+        .retain_mut(|stream| !stopped.contains(&stream.source()) && stream.service(&mut callbacks));
+
+    // Checked after the streams, which restart the sources that ran out of
+    // samples
+    let mut index = 0;
+    while index < watches.len() {
+        match al::alGetState(watches[index].source) {
+            ffi::AL_PLAYING | ffi::AL_PAUSED => watches[index].started = true,
+            ffi::AL_STOPPED if watches[index].started => {
+                callbacks.push(watches.swap_remove(index).callback);
+                continue;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    // A paused stream needs nothing until it is resumed, which wakes the
+    // thread
+    let playing = streams
+        .iter()
+        .filter(|stream| al::alGetState(stream.source()) != ffi::AL_PAUSED);
+    let streams_timeout = if events {
+        playing
+            .map(|stream| {
+                let fallback = Duration::from_millis(EVENT_FALLBACK_INTERVAL_MS);
+                stream
+                    .wake_interval()
+                    .map_or(fallback, |wake| wake.min(fallback))
+            })
+            .min()
+    } else {
+        playing.map(|stream| stream.poll_interval()).min()
+    };
+    let ramps_timeout = ramps
+        .first()
+        .map(|_| Duration::from_millis(RAMP_INTERVAL_MS));
+    let watches_timeout = watches.first().map(|_| {
+        Duration::from_millis(if events {
+            EVENT_FALLBACK_INTERVAL_MS
+        } else {
+            WATCH_INTERVAL_MS
+        })
+    });
+    drop(jobs);
+
+    // The callbacks may use ears, they are called without the lock
+    for callback in callbacks {
+        callback();
+    }
+
+    vec![streams_timeout, ramps_timeout, watches_timeout]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+fn start_thread(receiver: Receiver<()>) -> () {
+    let thread = thread::Builder::new().name(String::from("ears-stream"));
+    thread
+        .spawn(move || {
+            let events = enable_events();
+            let mut timeout = None;
+            loop {
+                let woken = match timeout {
+                    Some(timeout) => !matches!(
+                        receiver.recv_timeout(timeout),
+                        Err(RecvTimeoutError::Disconnected)
+                    ),
+                    None => receiver.recv().is_ok(),
+                };
+                if !woken {
+                    break;
+                }
+                timeout = service_streams(events);
+            }
+        })
+        .unwrap();
+}
+
+// Ask OpenAL to report the played buffers and the state changes, so the
+// thread only wakes when there is something to do.
+//
+// Returns true if the AL_SOFT_events extension is available.
+fn enable_events() -> bool {
+    if !al::alIsExtensionPresent("AL_SOFT_events") {
+        return false;
+    }
+
+    let control: ffi::LPALEVENTCONTROLSOFT = match al::alGetProcAddress("alEventControlSOFT") {
+        Some(ptr) => unsafe { mem::transmute::<*mut c_void, ffi::LPALEVENTCONTROLSOFT>(ptr) },
+        None => return false,
+    };
+    let callback: ffi::LPALEVENTCALLBACKSOFT = match al::alGetProcAddress("alEventCallbackSOFT") {
+        Some(ptr) => unsafe { mem::transmute::<*mut c_void, ffi::LPALEVENTCALLBACKSOFT>(ptr) },
+        None => return false,
+    };
+
+    let types = [
+        ffi::AL_EVENT_TYPE_BUFFER_COMPLETED_SOFT,
+        ffi::AL_EVENT_TYPE_SOURCE_STATE_CHANGED_SOFT,
+    ];
+    unsafe {
+        callback(Some(on_event), ptr::null_mut());
+        control(types.len() as i32, types.as_ptr(), ffi::AL_TRUE);
+    }
+    al::openal_has_error().is_none()
+}
+
+// Called by OpenAL from its own thread, which must not call any AL
+// function: only wake the streaming thread.
+extern "C" fn on_event(
+    _event_type: i32,
+    _object: u32,
+    _param: u32,
+    _length: i32,
+    _message: *const c_char,
+    _user_param: *mut c_void,
+) {
+    let waker = match WAKER.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if let Some(ref sender) = *waker {
+        let _ = sender.send(());
+    }
+}
@@ -0,0 +1,282 @@
+//! Loop region stored in the comments of Ogg Vorbis and Opus files.
+//!
+//! libsndfile only exposes the standard tags, the LOOPSTART and LOOPLENGTH
+//! comments used by many game engines are read from the comment header.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::str::from_utf8;
+
+/// Size of the header of an Ogg page, before its segment table.
+const PAGE_HEADER_SIZE: usize = 27;
+
+/// Number of bytes read at most to find the comment header, which may hold
+/// large pictures.
+const HEADER_SEARCH_SIZE: usize = 16 * 1024 * 1024;
+
+/**
+ * Read the loop region from the comments at the start of a stream.
+ *
+ * The stream is left at the position it had before the call.
+ *
+ * # Return
+ * Some((start, end)) in frames, the end being excluded, if the stream is
+ * an Ogg file with LOOPSTART and LOOPLENGTH comments, None otherwise.
+ */
+pub fn read_loop_region<R: Read + Seek>(reader: &mut R) -> Option<(i64, i64)> {
+    let position = reader.stream_position().ok()?;
+    let packet = read_comment_packet(reader);
+    reader.seek(SeekFrom::Start(position)).ok()?;
+
+    parse_loop_region(&packet?)
+}
+
+/**
+ * Reassemble the comment header of an Ogg file from its pages.
+ *
+ * The comment header is the second packet of the first logical stream, it
+ * spans several pages when the comments are large.
+ *
+ * # Return
+ * The packet, None if the stream isn't an Ogg file or ends before it.
+ */
+fn read_comment_packet<R: Read>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut serial = None;
+    let mut packet_index = 0;
+    let mut packet = Vec::new();
+    let mut read = 0;
+
+    while read < HEADER_SEARCH_SIZE {
+        let mut header = [0u8; PAGE_HEADER_SIZE];
+        reader.read_exact(&mut header).ok()?;
+        if !header.starts_with(b"OggS") {
+            return None;
+        }
+        let mut segments = vec![0u8; header[PAGE_HEADER_SIZE - 1] as usize];
+        reader.read_exact(&mut segments).ok()?;
+        let mut data = vec![0u8; segments.iter().map(|length| *length as usize).sum()];
+        reader.read_exact(&mut data).ok()?;
+        read += PAGE_HEADER_SIZE + segments.len() + data.len();
+
+        // The pages of the other streams are interleaved with the headers
+        let page_serial = read_u32(&header, 14)?;
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        // A packet ends with the first segment shorter than 255 bytes
+        let mut index = 0;
+        for length in segments {
+            let length = length as usize;
+            if packet_index == 1 {
+                packet.extend_from_slice(&data[index..index + length]);
+            }
+            index += length;
+            if length < 255 {
+                if packet_index == 1 {
+                    return Some(packet);
+                }
+                packet_index += 1;
+            }
+        }
+    }
+    None
+}
+
+/**
+ * Find the loop region in the comment header packet of an Ogg file.
+ *
+ * # Return
+ * Some((start, end)) in frames, the end being excluded, if the comments
+ * hold a valid LOOPSTART and LOOPLENGTH, None otherwise.
+ */
+pub fn parse_loop_region(packet: &[u8]) -> Option<(i64, i64)> {
+    let comments = if packet.starts_with(b"\x03vorbis") {
+        &packet[7..]
+    } else if packet.starts_with(b"OpusTags") {
+        &packet[8..]
+    } else {
+        return None;
+    };
+
+    let mut start = None;
+    let mut length = None;
+    for comment in read_comments(comments) {
+        let mut parts = comment.splitn(2, '=');
+        let key = parts.next().unwrap_or("").to_uppercase();
+        let value = parts
+            .next()
+            .and_then(|value| value.trim().parse::<i64>().ok());
+        match key.as_str() {
+            "LOOPSTART" => start = value,
+            "LOOPLENGTH" => length = value,
+            _ => {}
+        }
+    }
+
+    match (start, length) {
+        (Some(start), Some(length)) if start >= 0 && length > 0 => Some((start, start + length)),
+        _ => None,
+    }
+}
+
+/// Read the comments following the vendor string, stopping at the first
+/// one not fully in bytes.
+fn read_comments(bytes: &[u8]) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let vendor_length = match read_u32(bytes, 0) {
+        Some(length) => length,
+        None => return comments,
+    };
+    let mut index = 4 + vendor_length;
+    let count = match read_u32(bytes, index) {
+        Some(count) => count,
+        None => return comments,
+    };
+    index += 4;
+
+    for _ in 0..count {
+        let length = match read_u32(bytes, index) {
+            Some(length) => length,
+            None => break,
+        };
+        index += 4;
+        if index + length > bytes.len() {
+            break;
+        }
+        if let Ok(comment) = from_utf8(&bytes[index..index + length]) {
+            comments.push(comment);
+        }
+        index += length;
+    }
+    comments
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> Option<usize> {
+    if index + 4 > bytes.len() {
+        return None;
+    }
+    Some(
+        bytes[index] as usize
+            | (bytes[index + 1] as usize) << 8
+            | (bytes[index + 2] as usize) << 16
+            | (bytes[index + 3] as usize) << 24,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(non_snake_case)]
+
+    use std::fs::File;
+    use std::io::{Cursor, Seek, SeekFrom};
+    use vorbis_comments::{parse_loop_region, read_loop_region};
+
+    fn comment_header(comments: &[&str]) -> Vec<u8> {
+        fn u32_le(bytes: &mut Vec<u8>, value: usize) {
+            for shift in 0..4 {
+                bytes.push((value >> (shift * 8)) as u8);
+            }
+        }
+
+        let mut bytes = b"\x03vorbis".to_vec();
+        u32_le(&mut bytes, 4);
+        bytes.extend_from_slice(b"ears");
+        u32_le(&mut bytes, comments.len());
+        for comment in comments {
+            u32_le(&mut bytes, comment.len());
+            bytes.extend_from_slice(comment.as_bytes());
+        }
+        bytes
+    }
+
+    // Lay the packets of a stream out in Ogg pages of at most
+    // `page_segments` segments
+    fn ogg_pages(serial: u8, packets: &[Vec<u8>], page_segments: usize) -> Vec<Vec<u8>> {
+        let mut segments = Vec::new();
+        for packet in packets {
+            let mut rest = packet.len();
+            loop {
+                let length = rest.min(255);
+                segments.push(length as u8);
+                rest -= length;
+                if length < 255 {
+                    break;
+                }
+            }
+        }
+        let data = packets.concat();
+
+        let mut pages = Vec::new();
+        let mut index = 0;
+        for lacing in segments.chunks(page_segments) {
+            let size: usize = lacing.iter().map(|length| *length as usize).sum();
+            let mut page = b"OggS".to_vec();
+            page.extend(vec![0u8; 10]);
+            page.extend_from_slice(&[serial, 0, 0, 0]);
+            page.extend(vec![0u8; 8]);
+            page.push(lacing.len() as u8);
+            page.extend_from_slice(lacing);
+            page.extend_from_slice(&data[index..index + size]);
+            index += size;
+            pages.push(page);
+        }
+        pages
+    }
+
+    fn ogg_file(comments: &[&str]) -> Vec<u8> {
+        let packets = [b"\x01vorbis".to_vec(), comment_header(comments)];
+        ogg_pages(1, &packets, 255).concat()
+    }
+
+    #[test]
+    fn vorbis_comments_loop_region_OK() -> () {
+        let header = comment_header(&["TITLE=Theme", "LOOPSTART=44100", "looplength=88200"]);
+        assert_eq!(parse_loop_region(&header), Some((44100, 132300)));
+    }
+
+    #[test]
+    fn vorbis_comments_loop_region_FAIL() -> () {
+        assert_eq!(
+            parse_loop_region(&comment_header(&["LOOPSTART=44100"])),
+            None
+        );
+        assert_eq!(
+            parse_loop_region(&comment_header(&["LOOPSTART=10", "LOOPLENGTH=0"])),
+            None
+        );
+        assert_eq!(parse_loop_region(b"RIFF"), None);
+        assert_eq!(read_loop_region(&mut Cursor::new(b"RIFF".to_vec())), None);
+    }
+
+    #[test]
+    fn vorbis_comments_read_keeps_position() -> () {
+        let mut reader = Cursor::new(ogg_file(&["LOOPSTART=1", "LOOPLENGTH=2"]));
+        reader.seek(SeekFrom::Start(0)).unwrap();
+
+        assert_eq!(read_loop_region(&mut reader), Some((1, 3)));
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn vorbis_comments_across_pages() -> () {
+        // A cover art much larger than a page before the loop comments
+        let picture = format!("METADATA_BLOCK_PICTURE={}", "A".repeat(100000));
+        let packets = [
+            b"\x01vorbis".to_vec(),
+            comment_header(&[&picture, "LOOPSTART=100", "LOOPLENGTH=50"]),
+        ];
+        let mut pages = ogg_pages(1, &packets, 16);
+        assert!(pages.len() > 2);
+        // A page of another stream between the pages of the comments
+        pages.insert(2, ogg_pages(2, &[b"other".to_vec()], 255).concat());
+
+        let mut reader = Cursor::new(pages.concat());
+        assert_eq!(read_loop_region(&mut reader), Some((100, 150)));
+    }
+
+    #[test]
+    fn vorbis_comments_without_loop() -> () {
+        let mut file = File::open("res/music.ogg").unwrap();
+        assert_eq!(read_loop_region(&mut file), None);
+    }
+}